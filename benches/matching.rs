@@ -0,0 +1,151 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use simple_path_match::{bench, CachedPathMatch, MatchScratch, PathMatch};
+
+fn compile(c: &mut Criterion) {
+    c.bench_function("compile/from_pattern", |b| {
+        b.iter(|| {
+            for pattern in bench::PATTERNS {
+                black_box(PathMatch::from_pattern(black_box(pattern), bench::SEPARATOR).unwrap());
+            }
+        });
+    });
+    c.bench_function("compile/builder", |b| b.iter(|| black_box(bench::corpus().unwrap())));
+}
+
+fn corpus(c: &mut Criterion) {
+    let matcher = bench::corpus().unwrap();
+    let mut scratch = MatchScratch::new();
+    c.bench_function("corpus/matches_with_scratch", |b| {
+        b.iter(|| {
+            for path in bench::PATHS {
+                black_box(matcher.matches_with_scratch(black_box(path), &mut scratch));
+            }
+        });
+    });
+    c.bench_function("corpus/matches_prefix", |b| {
+        b.iter(|| {
+            for path in bench::PATHS {
+                black_box(matcher.matches_prefix(black_box(path)));
+            }
+        });
+    });
+}
+
+fn cached(c: &mut Criterion) {
+    let matcher = bench::corpus().unwrap();
+    let repeated = &bench::PATHS[..8];
+    c.bench_function("cached/uncached", |b| {
+        b.iter(|| {
+            for path in repeated {
+                black_box(matcher.matches(black_box(path)));
+            }
+        });
+    });
+    let mut hits = CachedPathMatch::<8>::new(matcher.clone());
+    c.bench_function("cached/hits", |b| {
+        b.iter(|| {
+            for path in repeated {
+                black_box(hits.matches(black_box(path)));
+            }
+        });
+    });
+    // Cycling through more paths than the cache holds always misses
+    let mut misses = CachedPathMatch::<4>::new(matcher);
+    c.bench_function("cached/misses", |b| {
+        b.iter(|| {
+            for path in repeated {
+                black_box(misses.matches(black_box(path)));
+            }
+        });
+    });
+}
+
+fn repeated_names(c: &mut Criterion) {
+    let matcher = bench::repeated_names(1000).unwrap();
+    c.bench_function("repeated_names/minimize", |b| {
+        b.iter(|| {
+            let mut matcher = matcher.clone();
+            matcher.minimize();
+            black_box(matcher)
+        });
+    });
+    let mut minimized = matcher.clone();
+    minimized.minimize();
+    println!("repeated_names: {:?}", bench::tree_memory(&matcher));
+    println!("repeated_names/minimized: {:?}", bench::tree_memory(&minimized));
+    c.bench_function("repeated_names/matches_minimized", |b| {
+        b.iter(|| minimized.matches(black_box("c500/src/main.rs")));
+    });
+}
+
+fn many_literals(c: &mut Criterion) {
+    let matcher = bench::many_literals(1000).unwrap();
+    c.bench_function("many_literals/matches", |b| {
+        b.iter(|| matcher.matches(black_box("dir500/file500.txt")));
+    });
+    c.bench_function("many_literals/matches_prefix", |b| {
+        b.iter(|| matcher.matches_prefix(black_box("dir500")));
+    });
+}
+
+fn wide_wildcards(c: &mut Criterion) {
+    let matcher = bench::wide_wildcards(1000).unwrap();
+    c.bench_function("wide_wildcards/matches", |b| {
+        b.iter(|| matcher.matches(black_box("dir/name999_file")));
+    });
+    let mut scratch = MatchScratch::new();
+    c.bench_function("wide_wildcards/matches_with_scratch", |b| {
+        b.iter(|| matcher.matches_with_scratch(black_box("dir/name999_file"), &mut scratch));
+    });
+    c.bench_function("wide_wildcards/matches_prefix", |b| {
+        b.iter(|| matcher.matches_prefix(black_box("dir/name999_file/other")));
+    });
+}
+
+fn wide_suffixes(c: &mut Criterion) {
+    let matcher = bench::wide_suffixes(1000).unwrap();
+    c.bench_function("wide_suffixes/matches", |b| {
+        b.iter(|| matcher.matches(black_box("dir/file_name999")));
+    });
+}
+
+fn wide_fanout(c: &mut Criterion) {
+    let matcher = bench::wide_fanout(1000).unwrap();
+    c.bench_function("wide_fanout/matches", |b| {
+        b.iter(|| matcher.matches(black_box("dir999/file.txt")));
+    });
+    c.bench_function("wide_fanout/matches_prefix", |b| {
+        b.iter(|| matcher.matches_prefix(black_box("dir999")));
+    });
+}
+
+fn deep_tree(c: &mut Criterion) {
+    let matcher = bench::deep_tree(64).unwrap();
+    let components: Vec<String> = (0..64).map(|i| format!("d{}", i)).collect();
+    let path = format!("{}/file.txt", components.join(bench::SEPARATOR));
+    let prefix = components[..32].join(bench::SEPARATOR);
+    c.bench_function("deep_tree/matches", |b| b.iter(|| matcher.matches(black_box(&path))));
+    let mut scratch = MatchScratch::new();
+    c.bench_function("deep_tree/matches_with_scratch", |b| {
+        b.iter(|| matcher.matches_with_scratch(black_box(&path), &mut scratch));
+    });
+    c.bench_function("deep_tree/matches_prefix", |b| {
+        b.iter(|| matcher.matches_prefix(black_box(&prefix)));
+    });
+}
+
+criterion_group!(
+    benches,
+    compile,
+    corpus,
+    cached,
+    repeated_names,
+    many_literals,
+    wide_wildcards,
+    wide_suffixes,
+    wide_fanout,
+    deep_tree
+);
+criterion_main!(benches);
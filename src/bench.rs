@@ -0,0 +1,232 @@
+//! Constructors for the matchers measured by the benchmark harness. These
+//! are exposed so that the benchmarks only depend on the public API.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{Error, PathComponent, PathMatch, PathMatchBuilder, PathMatchNode};
+
+/// The separator used by every benchmark matcher.
+pub const SEPARATOR: &str = "/";
+
+fn build<I: IntoIterator<Item = String>>(patterns: I) -> Result<PathMatch, Error> {
+    let mut builder = PathMatchBuilder::new(SEPARATOR);
+    for pattern in patterns {
+        builder.add_pattern(&pattern)?;
+    }
+    builder.build()
+}
+
+/// A mix of patterns exercising each feature of the pattern syntax, taken
+/// from the test suite. Used to measure pattern compilation.
+pub const PATTERNS: &[&str] = &[
+    "a",
+    "a/b/",
+    "*.rs",
+    "src/**/*.rs",
+    "**/x*",
+    "/r/...",
+    "b/...",
+    "[!a]*/c",
+    "log<1-12>",
+    ".*",
+    "./pdfs/*.pdf",
+    "./*/*/prefix.*",
+    "x/**",
+    "^a",
+    "b$",
+    "*[.b]",
+    r"\*\.",
+    "a/b/c/d",
+    "[[:digit:]]*",
+    "{src,tests}/{a,b{c,d}}/*.{rs,toml}",
+    "a/b/../c/./d",
+];
+
+/// Paths to match against the matcher built from every pattern in
+/// [`PATTERNS`], covering both matching and non-matching paths.
+pub const PATHS: &[&str] = &[
+    "a",
+    "a/b/",
+    "src/lib.rs",
+    "src/a/b/c/lib.rs",
+    "q/r/s/xyz",
+    "/r/s/t",
+    "b/c/d",
+    "b/c",
+    "log7",
+    "log13",
+    ".hidden",
+    "pdfs/test.pdf",
+    "a/b/prefix.txt",
+    "tests/bd/main.toml",
+    "a/c/d",
+    "no/such/path.txt",
+];
+
+/// Builds a matcher from every pattern in [`PATTERNS`].
+pub fn corpus() -> Result<PathMatch, Error> {
+    build(PATTERNS.iter().map(|&pattern| pattern.into()))
+}
+
+/// Builds a matcher from `count` literal patterns of the form
+/// `dir{i}/file{i}.txt`.
+pub fn many_literals(count: usize) -> Result<PathMatch, Error> {
+    build((0..count).map(|i| format!("dir{}/file{}.txt", i, i)))
+}
+
+/// Builds a matcher from `count` patterns of the form `*/name{i}_*`, so that
+/// every name below the first component must be tested against every
+/// wildcard.
+pub fn wide_wildcards(count: usize) -> Result<PathMatch, Error> {
+    build((0..count).map(|i| format!("*/name{}_*", i)))
+}
+
+/// Builds a matcher from `count` patterns of the form `*/*_name{i}`, the
+/// suffix-only counterpart of `wide_wildcards`.
+pub fn wide_suffixes(count: usize) -> Result<PathMatch, Error> {
+    build((0..count).map(|i| format!("*/*_name{}", i)))
+}
+
+/// Builds a matcher from `count` patterns of the form `dir{i}/*.txt`, so
+/// that matching must look up the first component among `count` literal
+/// siblings.
+pub fn wide_fanout(count: usize) -> Result<PathMatch, Error> {
+    build((0..count).map(|i| format!("dir{}/*.txt", i)))
+}
+
+/// Builds a matcher from `depth` patterns, the longest of which has `depth`
+/// directory components. The pattern with `n` directories is
+/// `d0/d1/.../d{n-1}/*.txt`.
+pub fn deep_tree(depth: usize) -> Result<PathMatch, Error> {
+    build((1..=depth).map(|n| {
+        let mut components: Vec<String> = (0..n).map(|i| format!("d{}", i)).collect();
+        components.push("*.txt".into());
+        components.join(SEPARATOR)
+    }))
+}
+
+/// Builds a matcher from `count` patterns of the form
+/// `{a,b,c,d}{i}/src/{lib,main}.rs`. The names `src`, `lib.rs` and `main.rs`
+/// are repeated below every directory, and the subtrees below the four
+/// directories of each pattern are identical, so can be shared by
+/// `PathMatch::minimize`.
+pub fn repeated_names(count: usize) -> Result<PathMatch, Error> {
+    build((0..count).map(|i| format!("{{a,b,c,d}}{}/src/{{lib,main}}.rs", i)))
+}
+
+/// The memory used by the tree of a matcher, counting each shared subtree
+/// once and ignoring allocator overhead
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeMemory {
+    /// The number of distinct nodes
+    pub nodes: usize,
+
+    /// The bytes occupied by the nodes and by the entries for their literal
+    /// children and chains, excluding the names themselves
+    pub node_bytes: usize,
+
+    /// The bytes occupied by literal names
+    pub name_bytes: usize,
+
+    /// The bytes literal names would occupy if each distinct name were
+    /// stored once, as by an interner
+    pub distinct_name_bytes: usize,
+}
+
+/// Measures the memory used by the tree of `matcher`.
+#[must_use]
+pub fn tree_memory(matcher: &PathMatch) -> TreeMemory {
+    fn visit<'a>(
+        node: &'a PathMatchNode,
+        seen: &mut BTreeSet<*const PathMatchNode>,
+        names: &mut BTreeMap<&'a str, usize>,
+        memory: &mut TreeMemory,
+    ) {
+        if !seen.insert(core::ptr::from_ref(node)) {
+            return;
+        }
+        memory.nodes += 1;
+        memory.node_bytes += core::mem::size_of::<PathMatchNode>()
+            + node.literals.len() * core::mem::size_of::<(PathComponent, alloc::sync::Arc<PathMatchNode>)>()
+            + node.chain.len() * core::mem::size_of::<PathComponent>();
+        for component in node.literals.keys().chain(&node.chain) {
+            if let PathComponent::Name(name) | PathComponent::RootName(name) = component {
+                memory.name_bytes += name.len();
+                *names.entry(name).or_default() += 1;
+            }
+        }
+        node.children().for_each(|child| visit(child, seen, names, memory));
+    }
+
+    let mut memory = TreeMemory {
+        nodes: 0,
+        node_bytes: 0,
+        name_bytes: 0,
+        distinct_name_bytes: 0,
+    };
+    let mut names = BTreeMap::new();
+    visit(&matcher.match_tree, &mut BTreeSet::new(), &mut names, &mut memory);
+    memory.distinct_name_bytes = names.keys().map(|name| name.len()).sum();
+    memory
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn workloads() -> Result<(), Error> {
+        let literals = many_literals(100)?;
+        assert!(literals.is_literal());
+        assert!(literals.matches("dir42/file42.txt"));
+        assert!(!literals.matches("dir42/file43.txt"));
+        assert!(!literals.matches("dir100/file100.txt"));
+
+        let wildcards = wide_wildcards(100)?;
+        assert!(wildcards.matches("x/name99_y"));
+        assert!(!wildcards.matches("x/name100_y"));
+        assert!(!wildcards.matches("name1_y"));
+
+        let suffixes = wide_suffixes(100)?;
+        assert!(suffixes.matches("x/y_name99"));
+        assert!(!suffixes.matches("x/y_name100"));
+        assert!(!suffixes.matches("y_name1"));
+
+        let fanout = wide_fanout(100)?;
+        assert!(!fanout.is_literal());
+        assert!(fanout.matches("dir99/a.txt"));
+        assert!(!fanout.matches("dir100/a.txt"));
+
+        let deep = deep_tree(10)?;
+        assert_eq!(deep.max_depth(), 11);
+        assert!(deep.matches("d0/a.txt"));
+        assert!(deep.matches("d0/d1/d2/d3/d4/d5/d6/d7/d8/d9/a.txt"));
+        assert!(!deep.matches("d0/d1/d2/d3/d4/d5/d6/d7/d8/d9/d10/a.txt"));
+        assert!(deep.matches_prefix("d0/d1/d2"));
+
+        let corpus = corpus()?;
+        assert!(corpus.matches("src/a/b/c/lib.rs"));
+        assert!(corpus.matches("tests/bd/main.toml"));
+        assert!(corpus.matches("a/c/d"));
+        assert!(!corpus.matches("no/such/path.txt"));
+        for pattern in PATTERNS {
+            PathMatch::from_pattern(pattern, SEPARATOR)?;
+        }
+
+        let mut repeated = repeated_names(100)?;
+        assert!(repeated.matches("c42/src/main.rs"));
+        assert!(!repeated.matches("e42/src/main.rs"));
+        let before = tree_memory(&repeated);
+        repeated.minimize();
+        let after = tree_memory(&repeated);
+        assert_eq!((before.nodes, after.nodes), (1201, 201));
+        // Minimizing already shares most repeated names, and interning the
+        // rest would save under 5% of the tree
+        assert!(after.name_bytes < before.name_bytes / 2);
+        assert!((after.name_bytes - after.distinct_name_bytes) * 20 < after.node_bytes + after.name_bytes);
+        Ok(())
+    }
+}
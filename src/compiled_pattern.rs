@@ -0,0 +1,175 @@
+use alloc::vec::Vec;
+
+use crate::{EscapedComponent, NameMatcher, PathComponent, PathMatchNode, PatternComponent, PATH_CURRENT, UNIX_SEP};
+
+/// A pattern held by a `PathMatch`, as returned by `PathMatch::patterns`.
+///
+/// This is reconstructed from the match tree rather than being the pattern
+/// originally added, so `.` and `..` components have been normalized away,
+/// braces expanded, and root names are written in their canonical form. The
+/// `Display` implementation writes the pattern using `/` as a separator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompiledPattern {
+    components: Vec<PatternComponent>,
+}
+
+impl alloc::fmt::Display for CompiledPattern {
+    fn fmt(&self, formatter: &mut alloc::fmt::Formatter<'_>) -> Result<(), alloc::fmt::Error> {
+        let mut previous: Option<&PatternComponent> = None;
+        for component in &self.components {
+            // A root is followed directly by the rest of the path, and an
+            // empty root is written as the separator
+            let after_root = matches!(
+                previous,
+                Some(
+                    PatternComponent::Literal(PathComponent::RootName(_))
+                        | PatternComponent::Wildcard(NameMatcher::Root(_))
+                )
+            );
+            let empty_root = *component == PatternComponent::Literal(PathComponent::RootName("".into()));
+            if previous.is_some() && !after_root && !empty_root {
+                formatter.write_str(UNIX_SEP)?;
+            }
+            match component {
+                _ if empty_root => formatter.write_str(UNIX_SEP)?,
+                PatternComponent::Literal(literal) => EscapedComponent(literal).fmt(formatter)?,
+                component => component.fmt(formatter)?,
+            }
+            previous = Some(component);
+        }
+        // A pattern ending at an empty root is written as `/.`, since `/`
+        // alone denotes a root followed by a separator
+        if previous == Some(&PatternComponent::Literal(PathComponent::RootName("".into()))) {
+            formatter.write_str(PATH_CURRENT)?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends a pattern to `result` for each pattern ending in the subtree of
+/// `node`, which is reached after the components in `prefix`.
+fn collect(node: &PathMatchNode, prefix: &mut Vec<PatternComponent>, result: &mut Vec<CompiledPattern>) {
+    let prefix_len = prefix.len();
+    prefix.extend(node.chain.iter().cloned().map(PatternComponent::Literal));
+    if node.can_end {
+        result.push(CompiledPattern {
+            components: prefix.clone(),
+        });
+    }
+    if node.matches_descendants {
+        let mut components = prefix.clone();
+        components.push(PatternComponent::AnyPath);
+        result.push(CompiledPattern { components });
+    }
+    // Literals are sorted so that the order is deterministic
+    let mut literals: Vec<_> = node.literals.iter().collect();
+    literals.sort_unstable_by_key(|&(k, _)| k);
+    let literals = literals
+        .into_iter()
+        .map(|(k, v)| (PatternComponent::Literal(k.clone()), v));
+    let wildcards = node
+        .wildcards
+        .iter()
+        .map(|(k, v)| (PatternComponent::Wildcard(k.clone()), v));
+    let globstar = node.globstar.iter().map(|v| (PatternComponent::GlobStar, v));
+    for (component, child) in literals.chain(wildcards).chain(globstar) {
+        prefix.push(component);
+        collect(child, prefix, result);
+        prefix.pop();
+    }
+    prefix.truncate(prefix_len);
+}
+
+/// Returns every pattern ending in the tree rooted at `node`.
+pub(crate) fn patterns(node: &PathMatchNode) -> Vec<CompiledPattern> {
+    let mut result = Vec::new();
+    collect(node, &mut Vec::new(), &mut result);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::{String, ToString as _};
+
+    use crate::{Error, PathMatch, PathMatchBuilder};
+
+    #[test]
+    fn reconstruction() -> Result<(), Error> {
+        let patterns = [
+            "a",
+            "a/b/",
+            "a/b/c/d",
+            "src/**/*.rs",
+            "/etc/...",
+            "docs/{a,b}",
+            "x/../y",
+            "[!a]*/c",
+            "log<1-12>",
+            r"\*literal/\.\.\.",
+            "...",
+            ".",
+            "./",
+            "/",
+        ];
+        let matcher = PathMatch::try_from_patterns("/", patterns)?;
+        let mut displayed: Vec<String> = matcher.patterns().map(|p| p.to_string()).collect();
+        let restored = PathMatch::try_from_patterns("/", &displayed)?;
+        assert_eq!(restored, matcher);
+        displayed.sort();
+        assert_eq!(
+            displayed,
+            [
+                ".",
+                "...",
+                "./",
+                "/",
+                "/etc/...",
+                r"[!a]*/c",
+                r"\*literal/\...",
+                "a",
+                "a/b/",
+                "a/b/c/d",
+                "docs/a",
+                "docs/b",
+                "log<1-12>",
+                "src/**/*.rs",
+                "y",
+            ]
+        );
+
+        let mut builder = PathMatchBuilder::new_for_platform(crate::platform_properties::Windows);
+        builder.add_patterns(["C:/a/*", "c:b", "/x"])?;
+        let matcher = builder.build()?;
+        let mut builder = PathMatchBuilder::new_for_platform(crate::platform_properties::Windows);
+        builder.add_patterns(matcher.patterns().map(|p| p.to_string()))?;
+        assert_eq!(builder.build()?, matcher);
+        Ok(())
+    }
+
+    #[test]
+    fn reconstruction_matches_same_paths() -> Result<(), Error> {
+        let patterns = ["/", "/.", "/a/..", "a/", "a/..", ".", "./", "/x/", "/x/*", "..."];
+        let paths = ["/", "/.", "/a", "/x/", "/x/y", ".", "./", "a", "a/", "b/c"];
+        let matcher = PathMatch::try_from_patterns("/", patterns)?;
+        for pattern in patterns {
+            let original = PathMatch::from_pattern(pattern, "/")?;
+            let compiled: Vec<_> = original.patterns().collect();
+            assert_eq!(compiled.len(), 1, "{}", pattern);
+            let restored = PathMatch::from_pattern(&compiled[0].to_string(), "/")?;
+            for path in paths {
+                assert_eq!(
+                    restored.matches(path),
+                    original.matches(path),
+                    "{} {}",
+                    compiled[0],
+                    path
+                );
+            }
+        }
+        for pattern in matcher.patterns() {
+            let restored = PathMatch::from_pattern(&pattern.to_string(), "/")?;
+            assert_eq!(restored.patterns().collect::<Vec<_>>(), [pattern]);
+        }
+        Ok(())
+    }
+}
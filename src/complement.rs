@@ -0,0 +1,60 @@
+use crate::PathMatch;
+
+/// Matches exactly the paths which a `PathMatch` does not.
+///
+/// Since the set of paths not matched by a pattern is almost always infinite,
+/// this is implemented by negating the result of the wrapped matcher rather
+/// than by constructing a new pattern tree.
+#[derive(Clone, Debug)]
+pub struct NotPathMatch {
+    inner: PathMatch,
+}
+
+impl NotPathMatch {
+    /// Constructs a `NotPathMatch` which matches the complement of `inner`.
+    #[must_use]
+    pub fn new(inner: PathMatch) -> NotPathMatch {
+        NotPathMatch { inner }
+    }
+
+    /// Returns `true` if the wrapped matcher does not match `path`.
+    pub fn matches<P: AsRef<str>>(&self, path: P) -> bool {
+        !self.inner.matches(path)
+    }
+
+    /// Returns `true` if `path` could be a prefix of a path which the wrapped
+    /// matcher does not match.
+    ///
+    /// Nearly every path can be extended into one which the wrapped matcher
+    /// rejects, so this conservatively returns `true`. Callers using prefix
+    /// matching to prune a directory traversal will therefore never skip a
+    /// directory containing a matching path.
+    pub fn matches_prefix<P: AsRef<str>>(&self, _path: P) -> bool {
+        true
+    }
+
+    /// Returns the wrapped `PathMatch`.
+    #[must_use]
+    pub fn into_inner(self) -> PathMatch {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn complement() -> Result<(), Error> {
+        let matcher = PathMatch::from_pattern("a/*.txt", "/")?;
+        let complement = matcher.clone().complement();
+        for path in ["a/b.txt", "a/c.txt/", "a/b.png", "b/c.txt", "a", "."] {
+            assert_eq!(complement.matches(path), !matcher.matches(path));
+            assert!(complement.matches_prefix(path));
+        }
+        assert!(complement.matches("a/b.png"));
+        assert!(!complement.matches("a/b.txt"));
+        Ok(())
+    }
+}
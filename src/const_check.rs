@@ -0,0 +1,309 @@
+//! Validation of pattern literals at compile time, used by the `path_match!`
+//! macro. Compiling a pattern requires allocation, so cannot be done in a
+//! `const` context. Instead, `check_pattern` rejects most invalid patterns
+//! while only examining the bytes of the pattern.
+
+/// Expands to an expression evaluating to a `PathMatch` compiled from a
+/// pattern, rejecting invalid patterns at compile time where it can.
+///
+/// The pattern must be a constant `&str`. It is passed to `check_pattern` in
+/// a `const` item, so that a pattern rejected by it fails the build. The
+/// expansion then calls `PathMatch::from_pattern` with the pattern and the
+/// separator, which defaults to `/`, and panics should this fail. This can
+/// only happen for errors not detected by `check_pattern`, such as an empty
+/// separator or an invalid component within a brace group.
+///
+/// ```
+/// use simple_path_match::path_match;
+///
+/// let matcher = path_match!("src/**/*.rs");
+/// assert!(matcher.matches("src/a/lib.rs"));
+/// let matcher = path_match!("docs/*.md", r"\");
+/// assert!(matcher.matches(r"docs\README.md"));
+/// ```
+///
+/// Components containing more than one wildcard are rejected:
+///
+/// ```compile_fail
+/// let matcher = simple_path_match::path_match!("src/*.*");
+/// ```
+///
+/// as are parent traversals escaping the start of the pattern:
+///
+/// ```compile_fail
+/// let matcher = simple_path_match::path_match!("src/../../lib.rs");
+/// ```
+///
+/// and malformed syntax:
+///
+/// ```compile_fail
+/// let matcher = simple_path_match::path_match!("src/{a,b");
+/// ```
+#[macro_export]
+macro_rules! path_match {
+    ($pattern:expr) => {
+        $crate::path_match!($pattern, "/")
+    };
+    ($pattern:expr, $separator:expr) => {{
+        const _: () = if let ::core::result::Result::Err(message) = $crate::check_pattern($pattern) {
+            ::core::panic!("{}", message)
+        };
+        $crate::PathMatch::from_pattern($pattern, $separator).expect("invalid pattern")
+    }};
+}
+
+const SEPARATOR: u8 = b'/';
+const ESCAPE: u8 = b'\\';
+const WILDCARD: u8 = b'*';
+const BRACE_START: u8 = b'{';
+const BRACE_END: u8 = b'}';
+const CLASS_START: u8 = b'[';
+const CLASS_END: u8 = b']';
+const CLASS_NEGATE: u8 = b'!';
+const NAMED_CLASS_DELIMITER: u8 = b':';
+
+/// Checks `pattern` for errors which can be found without compiling it,
+/// returning a description of the first error found. This is usable in a
+/// `const` context, and is called by the `path_match!` macro.
+///
+/// As with `PathMatch::from_pattern`, a `..` component is rejected unless
+/// it can be normalized away, so `a/../b` is accepted while `../b` and
+/// `a/**/..` are not. Unbalanced braces, incomplete escapes, unterminated
+/// character classes and components with more than one `*` are also
+/// rejected, but components containing braces are only checked for
+/// incomplete escapes, and are treated as a single name. A pattern accepted
+/// by this function may still be rejected by `PathMatch::from_pattern`.
+pub const fn check_pattern(pattern: &str) -> Result<(), &'static str> {
+    let bytes = pattern.as_bytes();
+    if !braces_balanced(bytes) {
+        return Err("Unbalanced braces in pattern");
+    }
+    // The names a `..` may remove, and whether a root precedes them, in
+    // which case a `..` with no name to remove has no effect
+    let mut names = 0usize;
+    let mut after_root = !bytes.is_empty() && bytes[0] == SEPARATOR;
+    let mut start = 0;
+    while start <= bytes.len() {
+        let end = component_end(bytes, start);
+        if let Err(message) = check_component(bytes, start, end) {
+            return Err(message);
+        }
+        match component_kind(bytes, start, end) {
+            ComponentKind::Name => names += 1,
+            ComponentKind::Parent if names > 0 => names -= 1,
+            ComponentKind::Parent if !after_root => return Err("Pattern must not contain parent traversals"),
+            ComponentKind::Parent | ComponentKind::Empty => {}
+            ComponentKind::Globstar => {
+                names = 0;
+                after_root = false;
+            }
+        }
+        start = end + 1;
+    }
+    Ok(())
+}
+
+/// How a component of a pattern affects the handling of a later `..`
+enum ComponentKind {
+    /// An empty component, the current directory or the root
+    Empty,
+    Parent,
+    /// A `**` or `...`, which a `..` cannot follow
+    Globstar,
+    Name,
+}
+
+const fn component_kind(bytes: &[u8], start: usize, end: usize) -> ComponentKind {
+    if component_is(bytes, start, end, b"") || component_is(bytes, start, end, b".") {
+        ComponentKind::Empty
+    } else if component_is(bytes, start, end, b"..") {
+        ComponentKind::Parent
+    } else if component_is(bytes, start, end, b"**") || component_is(bytes, start, end, b"...") {
+        ComponentKind::Globstar
+    } else {
+        ComponentKind::Name
+    }
+}
+
+const fn component_is(bytes: &[u8], start: usize, end: usize, text: &[u8]) -> bool {
+    if end - start != text.len() {
+        return false;
+    }
+    let mut idx = 0;
+    while idx < text.len() {
+        if bytes[start + idx] != text[idx] {
+            return false;
+        }
+        idx += 1;
+    }
+    true
+}
+
+const fn braces_balanced(bytes: &[u8]) -> bool {
+    let mut depth = 0usize;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            ESCAPE => idx += 1,
+            BRACE_START => depth += 1,
+            BRACE_END => {
+                if depth == 0 {
+                    return false;
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+    depth == 0
+}
+
+const fn component_end(bytes: &[u8], start: usize) -> usize {
+    let mut idx = start;
+    while idx < bytes.len() && bytes[idx] != SEPARATOR {
+        idx += 1;
+    }
+    idx
+}
+
+const fn check_component(bytes: &[u8], start: usize, end: usize) -> Result<(), &'static str> {
+    let mut has_brace = false;
+    let mut idx = start;
+    while idx < end {
+        match bytes[idx] {
+            ESCAPE => {
+                if idx + 1 == end {
+                    return Err("Component ends with an incomplete escape");
+                }
+                idx += 1;
+            }
+            BRACE_START | BRACE_END => has_brace = true,
+            _ => {}
+        }
+        idx += 1;
+    }
+    if has_brace {
+        // Classes and wildcards are only meaningful after brace expansion
+        return Ok(());
+    }
+    let mut wildcards = 0;
+    let mut idx = start;
+    while idx < end {
+        match bytes[idx] {
+            ESCAPE => idx += 1,
+            WILDCARD => wildcards += 1,
+            CLASS_START => match class_end(bytes, idx, end) {
+                Some(class_end) => idx = class_end - 1,
+                None => return Err("Unterminated character class in component"),
+            },
+            _ => {}
+        }
+        idx += 1;
+    }
+    let is_globstar = end - start == 2 && wildcards == 2;
+    if wildcards > 1 && !is_globstar {
+        return Err("Only one wildcard allowed in component");
+    }
+    Ok(())
+}
+
+/// Returns the index following the end of the class starting at `start`. As
+/// when parsing patterns, a `]` immediately after the opening bracket or
+/// negation is a member of the class, and named classes are skipped whole.
+const fn class_end(bytes: &[u8], start: usize, end: usize) -> Option<usize> {
+    let mut idx = start + 1;
+    if idx < end && bytes[idx] == CLASS_NEGATE {
+        idx += 1;
+    }
+    let mut first = true;
+    while idx < end {
+        if let Some(len) = named_class_len(bytes, idx, end) {
+            idx += len;
+        } else if bytes[idx] == CLASS_END && !first {
+            return Some(idx + 1);
+        } else {
+            idx += 1;
+        }
+        first = false;
+    }
+    None
+}
+
+const fn named_class_len(bytes: &[u8], start: usize, end: usize) -> Option<usize> {
+    if start + 1 >= end || bytes[start] != CLASS_START || bytes[start + 1] != NAMED_CLASS_DELIMITER {
+        return None;
+    }
+    let mut idx = start + 2;
+    while idx < end && bytes[idx].is_ascii_alphabetic() {
+        idx += 1;
+    }
+    if idx + 1 < end && bytes[idx] == NAMED_CLASS_DELIMITER && bytes[idx + 1] == CLASS_END {
+        Some(idx + 2 - start)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Error, PathMatch};
+
+    #[test]
+    fn checks() -> Result<(), Error> {
+        const PATTERN: &str = "docs/";
+
+        let valid = [
+            "",
+            "/",
+            "a/b/",
+            "src/**/*.rs",
+            "*.{rs,toml}",
+            "{*.rs,*.toml}",
+            r"\*\*/a*",
+            "[]*]x",
+            "[!]]*",
+            "[[:digit:]*]?",
+            "C:/foo/...",
+            "log<1-10>*",
+            "a/../b",
+            "a/b/../../c",
+            "/..",
+            "/a/../../b",
+            "./a/./..",
+        ];
+        for pattern in valid {
+            assert_eq!(check_pattern(pattern), Ok(()), "{}", pattern);
+            PathMatch::from_pattern(pattern, "/")?;
+        }
+        let invalid = [
+            "..",
+            "a/../..",
+            "./../a",
+            "a/**/..",
+            "**/a/../..",
+            "*.*",
+            "a/**b*",
+            "{a,b",
+            "a}",
+            r"a\",
+            r"a\/b",
+            "a/[bc",
+            "[/]",
+            "[[:alpha:]",
+        ];
+        for pattern in invalid {
+            assert!(check_pattern(pattern).is_err(), "{}", pattern);
+        }
+        // Not detected until the pattern is compiled
+        assert_eq!(check_pattern("{*.*,a}"), Ok(()));
+        assert!(PathMatch::from_pattern("{*.*,a}", "/").is_err());
+
+        let matcher = path_match!("src/*.rs");
+        assert!(matcher.matches("src/lib.rs"));
+        assert!(path_match!("a/../b").matches("b"));
+        assert!(path_match!(PATTERN, r"\").matches(r"docs\"));
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use alloc::string::{String, ToString as _};
+use alloc::vec::Vec;
+
+use crate::name_pattern::Segment;
+use crate::{EscapedComponent, NameMatcher, PathComponent, PatternComponent, PatternId, Redundancy};
+
+/// Problems with the patterns added to a `PathMatchBuilder` which do not
+/// prevent it from being built, as reported by
+/// `PathMatchBuilder::build_with_diagnostics`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    /// Patterns matching no path that another pattern does not
+    pub redundant: Vec<Redundancy>,
+
+    /// Pattern components which can never match because they contain a
+    /// separator of the paths being matched
+    pub unmatchable: Vec<UnmatchableComponent>,
+}
+
+/// A component of a pattern added to a `PathMatchBuilder` whose literal text
+/// contains a path separator. Paths are split at every separator, so no
+/// name within a path contains one and the component never matches.
+///
+/// This can only happen where the separator is not `/`, for instance a
+/// component written `a\\b` when matching paths separated by `\`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnmatchableComponent {
+    /// The pattern containing the component
+    pub pattern: PatternId,
+
+    /// The component, written in pattern syntax
+    pub component: String,
+}
+
+/// Returns the literal text which any name matched by `matcher` must contain.
+fn literal_text(matcher: &NameMatcher) -> Vec<&str> {
+    match matcher {
+        NameMatcher::StartsWith(text) | NameMatcher::EndsWith(text) => alloc::vec![text.as_str()],
+        NameMatcher::StartsEndsWith(m) => alloc::vec![m.0.as_str(), m.1.as_str()],
+        NameMatcher::Pattern(m) => m
+            .segments()
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Literal(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect(),
+        // Roots are split from paths before separators are considered
+        NameMatcher::Root(_) => Vec::new(),
+        NameMatcher::NoLeadingDot(m) => literal_text(m),
+    }
+}
+
+/// Returns `true` if every name matched by `component` must contain one of
+/// `separators`.
+fn contains_separator(component: &PatternComponent, separators: &[String]) -> bool {
+    let contains = |text: &str| separators.iter().any(|separator| text.contains(separator.as_str()));
+    match component {
+        PatternComponent::Literal(PathComponent::Name(name)) => contains(name),
+        PatternComponent::Wildcard(matcher) => literal_text(matcher).into_iter().any(contains),
+        _ => false,
+    }
+}
+
+/// Returns the components of `patterns` containing any of `separators`, once
+/// for each pattern containing them.
+pub(crate) fn unmatchable(patterns: &[Vec<Vec<PatternComponent>>], separators: &[String]) -> Vec<UnmatchableComponent> {
+    let mut result = Vec::new();
+    for (id, expansions) in patterns.iter().enumerate() {
+        let mut components: Vec<String> = expansions
+            .iter()
+            .flatten()
+            .filter(|component| contains_separator(component, separators))
+            .map(|component| match component {
+                PatternComponent::Literal(literal) => EscapedComponent(literal).to_string(),
+                component => component.to_string(),
+            })
+            .collect();
+        components.sort();
+        components.dedup();
+        result.extend(components.into_iter().map(|component| UnmatchableComponent {
+            pattern: PatternId(id),
+            component,
+        }));
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Error, PathMatchBuilder};
+
+    #[test]
+    fn separator_in_component() -> Result<(), Error> {
+        let mut builder = PathMatchBuilder::new(r"\");
+        builder.add_patterns([r"a\\b/c", "d/*.rs", r"{x,y\\z}/*\\", r"e\\*"])?;
+        let (matcher, diagnostics) = builder.build_with_diagnostics()?;
+        assert!(!matcher.matches(r"a\b\c"));
+        let unmatchable: Vec<_> = diagnostics
+            .unmatchable
+            .iter()
+            .map(|u| (u.pattern.0, u.component.as_str()))
+            .collect();
+        assert_eq!(unmatchable, [(0, r"a\\b"), (2, r"*\\"), (2, r"y\\z"), (3, r"e\\*")]);
+
+        // The same patterns are fine when paths are separated by `/`
+        let mut builder = PathMatchBuilder::new("/");
+        builder.add_patterns([r"a\\b/c", r"e\\*"])?;
+        let (matcher, diagnostics) = builder.build_with_diagnostics()?;
+        assert!(matcher.matches(r"a\b/c"));
+        assert!(diagnostics.unmatchable.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+//! Parsing of the representation of a `PathMatch` produced by its `Display`
+//! implementation. The grammar is:
+//!
+//! ```text
+//! node        := alternative | "(" alternative ("|" alternative)+ ")"
+//! alternative := "..." | component ("/" component)* "$"? ("/" node)?
+//! ```
+//!
+//! where a `$` may also follow any component of an alternative other than
+//! the last. Each component is written in pattern syntax, with literal text
+//! escaped by `write_escaped`. A `$` marks the end of a pattern, and `...`
+//! the end of a pattern matching all descendants. An alternative, or a
+//! group, following a `/` continues the path of the preceding components.
+//! A pattern matching only the root is written `/.`, as `/` alone denotes a
+//! root followed by a separator.
+//! The empty string represents a matcher with no patterns.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::name_pattern::named_class_len;
+use crate::{Error, ANY_PATH, ESCAPE, UNIX_SEP};
+
+const GROUP_START: char = '(';
+const GROUP_END: char = ')';
+const ALTERNATIVE: char = '|';
+const TERMINAL: char = '$';
+const SEPARATOR: char = '/';
+const CLASS_START: char = '[';
+const CLASS_END: char = ']';
+const CLASS_NEGATE: char = '!';
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    patterns: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next_if_eq(&mut self, expected: char) -> bool {
+        let found = self.peek() == Some(expected);
+        if found {
+            self.position += expected.len_utf8();
+        }
+        found
+    }
+
+    fn error(&self) -> Error {
+        let len = self.peek().map_or(0, char::len_utf8);
+        Error::InvalidDisplay {
+            span: self.position..self.position + len,
+        }
+    }
+
+    fn node(&mut self, prefix: &mut Vec<&'a str>) -> Result<(), Error> {
+        if !self.next_if_eq(GROUP_START) {
+            return self.alternative(prefix);
+        }
+        let start = self.position;
+        let mut count = 0;
+        loop {
+            self.alternative(prefix)?;
+            count += 1;
+            if self.next_if_eq(GROUP_END) {
+                break;
+            }
+            if !self.next_if_eq(ALTERNATIVE) {
+                return Err(self.error());
+            }
+        }
+        if count < 2 {
+            // A node with a single option is never parenthesized
+            return Err(Error::InvalidDisplay { span: start - 1..start });
+        }
+        Ok(())
+    }
+
+    fn alternative(&mut self, prefix: &mut Vec<&'a str>) -> Result<(), Error> {
+        let (prefix_len, pattern_count, start) = (prefix.len(), self.patterns.len(), self.position);
+        loop {
+            let component = self.component()?;
+            prefix.push(component);
+            if component == ANY_PATH {
+                self.end_pattern(prefix);
+                break;
+            }
+            if self.next_if_eq(TERMINAL) {
+                self.end_pattern(prefix);
+            }
+            if !self.next_if_eq(SEPARATOR) {
+                break;
+            }
+            if self.peek() == Some(GROUP_START) {
+                self.node(prefix)?;
+                break;
+            }
+        }
+        prefix.truncate(prefix_len);
+        if self.patterns.len() == pattern_count {
+            // Every alternative leads to the end of at least one pattern
+            return Err(Error::InvalidDisplay {
+                span: start..self.position,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads a component, which ends at the first unescaped separator, `$`,
+    /// `|` or parenthesis outside a character class.
+    fn component(&mut self) -> Result<&'a str, Error> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            match c {
+                SEPARATOR | TERMINAL | ALTERNATIVE | GROUP_START | GROUP_END => break,
+                ESCAPE => {
+                    self.position += c.len_utf8();
+                    let escaped = self.peek().ok_or_else(|| self.error())?;
+                    self.position += escaped.len_utf8();
+                }
+                CLASS_START => self.class()?,
+                _ => self.position += c.len_utf8(),
+            }
+        }
+        Ok(&self.text[start..self.position])
+    }
+
+    /// Skips a character class. As when parsing patterns, a `]` immediately
+    /// after the opening bracket or negation is a member of the class, and
+    /// named classes such as `[:digit:]` are skipped whole.
+    fn class(&mut self) -> Result<(), Error> {
+        let start = self.position;
+        self.position += CLASS_START.len_utf8();
+        self.next_if_eq(CLASS_NEGATE);
+        let mut first = true;
+        while let Some(c) = self.peek() {
+            if let Some(len) = named_class_len(&self.text[self.position..]) {
+                self.position += len;
+                first = false;
+                continue;
+            }
+            self.position += c.len_utf8();
+            if c == CLASS_END && !first {
+                return Ok(());
+            }
+            first = false;
+        }
+        Err(Error::InvalidDisplay {
+            span: start..self.position,
+        })
+    }
+
+    fn end_pattern(&mut self, components: &[&str]) {
+        let pattern = match components {
+            // A root with no further components
+            [""] => String::from(UNIX_SEP),
+            _ => components.join(UNIX_SEP),
+        };
+        self.patterns.push(pattern);
+    }
+}
+
+/// Returns one pattern for each pattern ending in the matcher represented by
+/// `text`.
+pub(crate) fn patterns(text: &str) -> Result<Vec<String>, Error> {
+    let mut parser = Parser {
+        text,
+        position: 0,
+        patterns: Vec::new(),
+    };
+    if !text.is_empty() {
+        parser.node(&mut Vec::new())?;
+        if parser.position != text.len() {
+            return Err(parser.error());
+        }
+    }
+    Ok(parser.patterns)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grammar() -> Result<(), Error> {
+        assert!(patterns("")?.is_empty());
+        assert_eq!(patterns("a$")?, ["a"]);
+        assert_eq!(
+            patterns(r"(a/b/c$/d/$|/r/s$|x*/...|\*\*/**/y$|[)|]$/(...|\$$)|[[:alpha:]|]$)")?,
+            [
+                "a/b/c",
+                "a/b/c/d/",
+                "/r/s",
+                "x*/...",
+                r"\*\*/**/y",
+                "[)|]",
+                "[)|]/...",
+                r"[)|]/\$",
+                "[[:alpha:]|]"
+            ]
+        );
+
+        let cases = [
+            ("a", 0..1),
+            ("a/b", 0..3),
+            ("(a$)", 0..1),
+            ("(a$|b$", 6..6),
+            ("a$)", 2..3),
+            ("a$$", 2..3),
+            ("[ab$", 0..4),
+            (r"a\", 2..2),
+        ];
+        for (text, span) in cases {
+            let error = patterns(text).expect_err(text);
+            assert!(matches!(error, Error::InvalidDisplay { .. }));
+            assert_eq!(error.span(), Some(span), "{}", text);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,471 @@
+use alloc::string::{String, ToString as _};
+use alloc::vec::Vec;
+
+use crate::{Error, BRACE_END, BRACE_SEPARATOR, BRACE_START, ESCAPE, WILDCARD_ANY, WILDCARD_ONE};
+
+const RANGE_START: char = '<';
+const RANGE_SEPARATOR: char = '-';
+const RANGE_END: char = '>';
+const CLASS_START: char = '[';
+const CLASS_RANGE: char = '-';
+const CLASS_END: char = ']';
+const CLASS_NEGATE: char = '!';
+const NAMED_CLASS_START: &str = "[:";
+const NAMED_CLASS_END: &str = ":]";
+
+/// A POSIX character class such as `[:digit:]`, which may appear within a
+/// bracket expression. Only ASCII characters are members of these classes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub(crate) enum NamedClass {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    Xdigit,
+}
+
+/// The supported named classes, as written between `[:` and `:]`
+const NAMED_CLASSES: [(&str, NamedClass); 12] = [
+    ("alnum", NamedClass::Alnum),
+    ("alpha", NamedClass::Alpha),
+    ("blank", NamedClass::Blank),
+    ("cntrl", NamedClass::Cntrl),
+    ("digit", NamedClass::Digit),
+    ("graph", NamedClass::Graph),
+    ("lower", NamedClass::Lower),
+    ("print", NamedClass::Print),
+    ("punct", NamedClass::Punct),
+    ("space", NamedClass::Space),
+    ("upper", NamedClass::Upper),
+    ("xdigit", NamedClass::Xdigit),
+];
+
+impl NamedClass {
+    fn from_name(name: &str) -> Option<NamedClass> {
+        NAMED_CLASSES
+            .iter()
+            .find(|&&(candidate, _)| candidate == name)
+            .map(|&(_, class)| class)
+    }
+
+    fn name(self) -> &'static str {
+        NAMED_CLASSES
+            .iter()
+            .find(|&&(_, candidate)| candidate == self)
+            .map(|&(name, _)| name)
+            .expect("every class is named")
+    }
+
+    pub(crate) fn matches(self, c: char) -> bool {
+        match self {
+            NamedClass::Alnum => c.is_ascii_alphanumeric(),
+            NamedClass::Alpha => c.is_ascii_alphabetic(),
+            NamedClass::Blank => c == ' ' || c == '\t',
+            NamedClass::Cntrl => c.is_ascii_control(),
+            NamedClass::Digit => c.is_ascii_digit(),
+            NamedClass::Graph => c.is_ascii_graphic(),
+            NamedClass::Lower => c.is_ascii_lowercase(),
+            NamedClass::Print => c.is_ascii_graphic() || c == ' ',
+            NamedClass::Punct => c.is_ascii_punctuation(),
+            NamedClass::Space => c.is_ascii_whitespace(),
+            NamedClass::Upper => c.is_ascii_uppercase(),
+            NamedClass::Xdigit => c.is_ascii_hexdigit(),
+        }
+    }
+
+    /// Returns the members of the class as inclusive ranges, for use where
+    /// the predicate in `matches` cannot be.
+    pub(crate) fn ranges(self) -> &'static [(char, char)] {
+        match self {
+            NamedClass::Alnum => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+            NamedClass::Alpha => &[('A', 'Z'), ('a', 'z')],
+            NamedClass::Blank => &[('\t', '\t'), (' ', ' ')],
+            NamedClass::Cntrl => &[('\0', '\x1f'), ('\x7f', '\x7f')],
+            NamedClass::Digit => &[('0', '9')],
+            NamedClass::Graph => &[('!', '~')],
+            NamedClass::Lower => &[('a', 'z')],
+            NamedClass::Print => &[(' ', '~')],
+            NamedClass::Punct => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+            NamedClass::Space => &[('\t', '\n'), ('\x0c', '\r'), (' ', ' ')],
+            NamedClass::Upper => &[('A', 'Z')],
+            NamedClass::Xdigit => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+        }
+    }
+}
+
+/// Returns the length of the named class syntax, such as `[:digit:]`, at the
+/// start of `text`. The name itself is not checked, other than that it
+/// consists of ASCII letters.
+pub(crate) fn named_class_len(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix(NAMED_CLASS_START)?;
+    let name_len = rest.bytes().take_while(u8::is_ascii_alphabetic).count();
+    rest[name_len..]
+        .starts_with(NAMED_CLASS_END)
+        .then_some(NAMED_CLASS_START.len() + name_len + NAMED_CLASS_END.len())
+}
+
+/// A bracket expression matching a single character. The ranges are stored in
+/// the order written, with single characters as ranges of one. As in POSIX
+/// shells, a leading `!` negates the class, while `^` has no special meaning.
+/// Named classes such as `[:digit:]` are stored separately.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub(crate) struct CharClass {
+    pub(crate) negated: bool,
+    pub(crate) ranges: Vec<(char, char)>,
+    pub(crate) named: Vec<NamedClass>,
+}
+
+impl alloc::fmt::Display for CharClass {
+    fn fmt(&self, formatter: &mut alloc::fmt::Formatter<'_>) -> Result<(), alloc::fmt::Error> {
+        use alloc::fmt::Write as _;
+
+        formatter.write_char(CLASS_START)?;
+        if self.negated {
+            formatter.write_char(CLASS_NEGATE)?;
+        }
+        for &(low, high) in &self.ranges {
+            formatter.write_char(low)?;
+            if low != high {
+                formatter.write_char(CLASS_RANGE)?;
+                formatter.write_char(high)?;
+            }
+        }
+        // Named classes are written last so that a leading `]` stays first
+        for named in &self.named {
+            write!(formatter, "{}{}{}", NAMED_CLASS_START, named.name(), NAMED_CLASS_END)?;
+        }
+        formatter.write_char(CLASS_END)
+    }
+}
+
+impl CharClass {
+    /// Parses a bracket expression at the start of `text`, returning the class
+    /// and the length of its syntax. A `]` immediately following the opening
+    /// bracket (or negation) is treated as a member of the class rather than
+    /// its end. An error is returned for an unknown named class or a range
+    /// whose bounds are reversed, such as `z-a`.
+    fn parse(text: &str, component: &str) -> Result<(CharClass, usize), Error> {
+        let unterminated = || Error::UnterminatedClass {
+            component: component.to_string(),
+            span: 0..text.len(),
+        };
+        let mut chars = text.char_indices().skip(1).peekable();
+        let negated = chars.next_if(|&(_, c)| c == CLASS_NEGATE).is_some();
+        let mut ranges = Vec::new();
+        let mut named = Vec::new();
+        while let Some((idx, c)) = chars.next() {
+            if c == CLASS_END && !(ranges.is_empty() && named.is_empty()) {
+                return Ok((CharClass { negated, ranges, named }, idx + c.len_utf8()));
+            }
+            if let Some(len) = named_class_len(&text[idx..]) {
+                let name = &text[idx + NAMED_CLASS_START.len()..idx + len - NAMED_CLASS_END.len()];
+                let class = NamedClass::from_name(name).ok_or_else(|| Error::UnknownClass {
+                    component: component.to_string(),
+                    name: name.to_string(),
+                    span: idx..idx + len,
+                })?;
+                named.push(class);
+                while chars.next_if(|&(next, _)| next < idx + len).is_some() {}
+                continue;
+            }
+            let mut lookahead = chars.clone();
+            match (lookahead.next(), lookahead.next()) {
+                (Some((_, CLASS_RANGE)), Some((high_idx, high))) if high != CLASS_END => {
+                    if high < c {
+                        return Err(Error::InvalidRange {
+                            component: component.to_string(),
+                            span: idx..high_idx + high.len_utf8(),
+                        });
+                    }
+                    ranges.push((c, high));
+                    chars = lookahead;
+                }
+                _ => ranges.push((c, c)),
+            }
+        }
+        Err(unterminated())
+    }
+
+    pub(crate) fn matches(&self, c: char) -> bool {
+        let in_ranges = self.ranges.iter().any(|&(low, high)| (low..=high).contains(&c));
+        (in_ranges || self.named.iter().any(|named| named.matches(c))) != self.negated
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub(crate) enum Segment {
+    Literal(String),
+    Wildcard,
+    /// Between the given inclusive numbers of arbitrary characters
+    AnyChars(usize, usize),
+    /// A sequence of decimal digits whose value lies in the inclusive range
+    NumericRange(u64, u64),
+    Class(CharClass),
+}
+
+/// A matcher for names which cannot be expressed as a `StartsEndsWith`. The
+/// name is matched by a sequence of segments, backtracking where a segment
+/// could match more than one length of input.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub(crate) struct NamePattern(Vec<Segment>);
+
+impl alloc::fmt::Display for NamePattern {
+    fn fmt(&self, formatter: &mut alloc::fmt::Formatter<'_>) -> Result<(), alloc::fmt::Error> {
+        use alloc::fmt::Write as _;
+
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(literal) => crate::write_escaped(formatter, literal)?,
+                Segment::Wildcard => formatter.write_str(WILDCARD_ANY)?,
+                Segment::AnyChars(1, 1) => formatter.write_char(WILDCARD_ONE)?,
+                Segment::AnyChars(min, max) if min == max => {
+                    write!(formatter, "{}{}{}{}", WILDCARD_ONE, BRACE_START, min, BRACE_END)?;
+                }
+                Segment::AnyChars(min, max) => {
+                    write!(
+                        formatter,
+                        "{}{}{}{}{}{}",
+                        WILDCARD_ONE, BRACE_START, min, BRACE_SEPARATOR, max, BRACE_END
+                    )?;
+                }
+                Segment::Class(class) => class.fmt(formatter)?,
+                Segment::NumericRange(low, high) => {
+                    write!(
+                        formatter,
+                        "{}{}{}{}{}",
+                        RANGE_START, low, RANGE_SEPARATOR, high, RANGE_END
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a numeric range such as `<1-100>` or `[1-100]` at the start of
+/// `text`, returning the bounds and the length of the range syntax. A range
+/// in brackets must have a bound of more than one digit, so that `[0-9]`
+/// remains a character class. Returns `None` if `text` does not start with a
+/// range, and an error if the bounds are invalid.
+fn parse_range(text: &str, component: &str) -> Result<Option<(u64, u64, usize)>, Error> {
+    let (inner, in_class) = if let Some(inner) = text.strip_prefix(RANGE_START) {
+        let Some((inner, _)) = inner.split_once(RANGE_END) else {
+            return Ok(None);
+        };
+        (inner, false)
+    } else if let Some(inner) = text.strip_prefix(CLASS_START) {
+        let Some((inner, _)) = inner.split_once(CLASS_END) else {
+            return Ok(None);
+        };
+        (inner, true)
+    } else {
+        return Ok(None);
+    };
+    // Both delimiters are one byte long
+    let len = inner.len() + 2;
+    let invalid = || Error::InvalidRange {
+        component: component.to_string(),
+        span: 0..len,
+    };
+    let Some((low, high)) = inner.split_once(RANGE_SEPARATOR) else {
+        return Ok(None);
+    };
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !is_number(low) || !is_number(high) || (in_class && low.len() == 1 && high.len() == 1) {
+        return Ok(None);
+    }
+    let low: u64 = low.parse().map_err(|_| invalid())?;
+    let high: u64 = high.parse().map_err(|_| invalid())?;
+    if low > high {
+        return Err(invalid());
+    }
+    Ok(Some((low, high, len)))
+}
+
+/// Parses the quantifier following a `?` at the start of `text`, returning
+/// the bounds on the number of characters matched and the length of the
+/// quantifier. A quantifier is either `{n}` or `{min,max}`, and a `?` which
+/// is not followed by one matches a single character.
+fn parse_quantifier(text: &str, component: &str) -> Result<(usize, usize, usize), Error> {
+    let Some(inner) = text.strip_prefix(BRACE_START) else {
+        return Ok((1, 1, 0));
+    };
+    let len = inner
+        .find(BRACE_END)
+        .map_or(text.len(), |end| end + BRACE_START.len_utf8() + BRACE_END.len_utf8());
+    let invalid = || Error::InvalidQuantifier {
+        component: component.to_string(),
+        span: 0..len,
+    };
+    let inner = inner
+        .get(..len - BRACE_START.len_utf8() - BRACE_END.len_utf8())
+        .ok_or_else(invalid)?;
+    let parse = |bound: &str| -> Result<usize, Error> {
+        if bound.is_empty() || !bound.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        bound.parse().map_err(|_| invalid())
+    };
+    let (min, max) = if let Some((min, max)) = inner.split_once(BRACE_SEPARATOR) {
+        (parse(min)?, parse(max)?)
+    } else {
+        let count = parse(inner)?;
+        (count, count)
+    };
+    if min > max {
+        return Err(invalid());
+    }
+    Ok((min, max, len))
+}
+
+/// The simplest form of matcher for a parsed name
+pub(crate) enum ParsedName {
+    Literal(String),
+    StartsEndsWith(String, String),
+    Pattern(NamePattern),
+}
+
+impl NamePattern {
+    /// Parses `name`, which is all or part of `component`, in which
+    /// `wildcard` takes the place of `*`. A `\` causes the following
+    /// character to be matched literally. The spans of any errors are
+    /// relative to `name`.
+    pub fn parse(name: &str, component: &str, wildcard: char) -> Result<ParsedName, Error> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = name;
+        while let Some(c) = rest.chars().next() {
+            let position = name.len() - rest.len();
+            let mut end_literal = |segments: &mut Vec<Segment>| {
+                segments.extend((!literal.is_empty()).then(|| Segment::Literal(core::mem::take(&mut literal))));
+            };
+            if c == ESCAPE {
+                let mut chars = rest[c.len_utf8()..].chars();
+                let escaped = chars.next().ok_or_else(|| Error::TrailingEscape {
+                    component: component.to_string(),
+                    span: position..name.len(),
+                })?;
+                literal.push(escaped);
+                rest = chars.as_str();
+            } else if let Some((low, high, len)) =
+                parse_range(rest, component).map_err(|error| error.offset_span(position))?
+            {
+                end_literal(&mut segments);
+                segments.push(Segment::NumericRange(low, high));
+                rest = &rest[len..];
+            } else if c == CLASS_START {
+                let (class, len) = CharClass::parse(rest, component).map_err(|error| error.offset_span(position))?;
+                end_literal(&mut segments);
+                segments.push(Segment::Class(class));
+                rest = &rest[len..];
+            } else if let Some(tail) = rest.strip_prefix(WILDCARD_ONE) {
+                let offset = position + WILDCARD_ONE.len_utf8();
+                let (min, max, len) = parse_quantifier(tail, component).map_err(|error| error.offset_span(offset))?;
+                end_literal(&mut segments);
+                segments.push(Segment::AnyChars(min, max));
+                rest = &tail[len..];
+            } else if let Some(tail) = rest.strip_prefix(wildcard) {
+                if segments.contains(&Segment::Wildcard) {
+                    return Err(Error::WildcardPosition {
+                        component: component.to_string(),
+                        span: position..position + wildcard.len_utf8(),
+                    });
+                }
+                end_literal(&mut segments);
+                segments.push(Segment::Wildcard);
+                rest = tail;
+            } else {
+                literal.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        segments.extend((!literal.is_empty()).then_some(Segment::Literal(literal)));
+        let parsed = match segments.as_mut_slice() {
+            [] => ParsedName::Literal(String::new()),
+            [Segment::Literal(literal)] => ParsedName::Literal(core::mem::take(literal)),
+            [Segment::Wildcard] => ParsedName::StartsEndsWith(String::new(), String::new()),
+            [Segment::Literal(start), Segment::Wildcard] => {
+                ParsedName::StartsEndsWith(core::mem::take(start), String::new())
+            }
+            [Segment::Wildcard, Segment::Literal(end)] => {
+                ParsedName::StartsEndsWith(String::new(), core::mem::take(end))
+            }
+            [Segment::Literal(start), Segment::Wildcard, Segment::Literal(end)] => {
+                ParsedName::StartsEndsWith(core::mem::take(start), core::mem::take(end))
+            }
+            _ => ParsedName::Pattern(NamePattern(segments)),
+        };
+        Ok(parsed)
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        Self::matches_segments(&self.0, name)
+    }
+
+    pub(crate) fn segments(&self) -> &[Segment] {
+        &self.0
+    }
+
+    fn matches_segments(segments: &[Segment], name: &str) -> bool {
+        let Some((segment, rest)) = segments.split_first() else {
+            return name.is_empty();
+        };
+        match segment {
+            Segment::Literal(literal) => name
+                .strip_prefix(literal.as_str())
+                .is_some_and(|name| Self::matches_segments(rest, name)),
+            Segment::Wildcard => name
+                .char_indices()
+                .map(|(idx, _)| idx)
+                .chain(core::iter::once(name.len()))
+                .any(|idx| Self::matches_segments(rest, &name[idx..])),
+            Segment::AnyChars(min, max) => name
+                .char_indices()
+                .map(|(idx, _)| idx)
+                .chain(core::iter::once(name.len()))
+                .skip(*min)
+                .take(max - min + 1)
+                .any(|idx| Self::matches_segments(rest, &name[idx..])),
+            Segment::Class(class) => {
+                let mut chars = name.chars();
+                chars
+                    .next()
+                    .is_some_and(|c| class.matches(c) && Self::matches_segments(rest, chars.as_str()))
+            }
+            Segment::NumericRange(low, high) => {
+                let digits = name.bytes().take_while(u8::is_ascii_digit).count();
+                (1..=digits).any(|len| {
+                    // Values too large to parse necessarily exceed the upper bound
+                    let in_range = name[..len]
+                        .parse::<u64>()
+                        .is_ok_and(|value| (*low..=*high).contains(&value));
+                    in_range && Self::matches_segments(rest, &name[len..])
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn named_class_ranges() {
+        for (name, class) in NAMED_CLASSES {
+            assert_eq!(NamedClass::from_name(name), Some(class));
+            assert_eq!(class.name(), name);
+            for c in (0..=0x7f_u8).map(char::from).chain(['é', '\u{a0}']) {
+                let in_ranges = class.ranges().iter().any(|&(low, high)| (low..=high).contains(&c));
+                assert_eq!(class.matches(c), in_ranges, "{} {:?}", name, c);
+            }
+        }
+    }
+}
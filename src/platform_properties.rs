@@ -0,0 +1,301 @@
+//! Describes how paths are structured on a particular platform.
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::sync::Arc;
+use core::any::TypeId;
+
+/// Properties of the paths used by a platform
+pub trait PlatformProperties {
+    /// Returns the separators which may appear between the components of a
+    /// path. The first is the preferred separator.
+    fn separators(&self) -> &[char];
+
+    /// Returns the prefix of `path` which names its root, if it has a root
+    /// other than one introduced by a separator alone, e.g. a drive letter.
+    fn root_name<'a>(&self, path: &'a str) -> Option<&'a str>;
+
+    /// Returns the form of `root`, a root name found by `root_name`, which is
+    /// compared against the root names of patterns. Roots written
+    /// differently but naming the same location should share a form.
+    fn canonical_root<'a>(&self, root: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(root)
+    }
+
+    /// Returns the greatest number of separators a root name found by
+    /// `root_name` may contain. `StreamingMatcher` buffers this much of a
+    /// path before looking for a root name.
+    fn max_root_separators(&self) -> usize {
+        0
+    }
+
+    /// Returns `false` if names differing only in the case of ASCII letters
+    /// refer to the same file, in which case literal names are compared
+    /// ignoring ASCII case.
+    fn case_sensitive(&self) -> bool {
+        true
+    }
+}
+
+/// Paths on Unix-like platforms, which are separated by `/`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Unix;
+
+impl Unix {
+    /// The separators returned by `PlatformProperties::separators`
+    pub const SEPARATORS: &'static [char] = &['/'];
+}
+
+impl PlatformProperties for Unix {
+    fn separators(&self) -> &[char] {
+        Unix::SEPARATORS
+    }
+
+    fn root_name<'a>(&self, _path: &'a str) -> Option<&'a str> {
+        None
+    }
+
+    fn case_sensitive(&self) -> bool {
+        true
+    }
+}
+
+/// Paths on Windows, which may be separated by either `\` or `/`, and may
+/// start with a drive letter or a UNC prefix naming a share, as in
+/// `\\server\share\file`. Names are case-insensitive.
+///
+/// A drive letter not followed by a separator, as in `C:foo`, starts a path
+/// relative to the current directory of that drive. Such a path only matches
+/// a drive-relative pattern, and a `..` directly after the drive escapes it.
+///
+/// A UNC prefix consists of two separators followed by a server name, a
+/// separator and a share name, and forms a single root name. Its separators
+/// are compared as if they were all `\`, so the pattern `//server/share/*`
+/// matches `\\server\share\file`.
+///
+/// Verbatim paths such as `\\?\C:\foo` and `\\?\UNC\server\share` have
+/// a root name which is compared as the equivalent drive or UNC prefix, so
+/// the pattern `C:/foo` matches `\\?\C:\foo`. Windows does not normalize
+/// verbatim paths, but this is not modelled: their components are
+/// normalized, and may be separated by `/`, as for any other path.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Windows;
+
+impl Windows {
+    /// The separators returned by `PlatformProperties::separators`
+    pub const SEPARATORS: &'static [char] = &['\\', '/'];
+}
+
+impl PlatformProperties for Windows {
+    fn separators(&self) -> &[char] {
+        Windows::SEPARATORS
+    }
+
+    fn root_name<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if let Some(rest) = path.strip_prefix(VERBATIM_PREFIX) {
+            let len = if let Some(unc) = rest.strip_prefix(VERBATIM_UNC) {
+                VERBATIM_UNC.len() + share_len(unc, &['\\'])?
+            } else if let Some(drive) = drive_root(rest) {
+                drive.len()
+            } else {
+                // Another kind of device, such as a volume
+                rest.find('\\').unwrap_or(rest.len())
+            };
+            return (len > 0).then(|| &path[..VERBATIM_PREFIX.len() + len]);
+        }
+        drive_root(path).or_else(|| unc_root(path, self.separators()))
+    }
+
+    fn canonical_root<'a>(&self, root: &'a str) -> Cow<'a, str> {
+        if let Some(rest) = root.strip_prefix(VERBATIM_PREFIX) {
+            return match rest.strip_prefix(VERBATIM_UNC) {
+                Some(unc) => Cow::Owned(format!(r"\\{}", unc)),
+                None if drive_root(rest).is_some() => Cow::Borrowed(rest),
+                None => Cow::Borrowed(root),
+            };
+        }
+        if root.contains('/') {
+            Cow::Owned(root.replace('/', "\\"))
+        } else {
+            Cow::Borrowed(root)
+        }
+    }
+
+    fn max_root_separators(&self) -> usize {
+        // As in `\\?\UNC\server\share`
+        5
+    }
+
+    fn case_sensitive(&self) -> bool {
+        false
+    }
+}
+
+/// The prefix of a verbatim path, for which Windows performs no normalization
+const VERBATIM_PREFIX: &str = r"\\?\";
+
+/// Follows `VERBATIM_PREFIX` in a verbatim UNC path, such as
+/// `\\?\UNC\server\share`
+const VERBATIM_UNC: &str = r"UNC\";
+
+/// Returns the drive letter and colon at the start of `path`, if present.
+fn drive_root(path: &str) -> Option<&str> {
+    match path.as_bytes() {
+        [drive, b':', ..] if drive.is_ascii_alphabetic() => Some(&path[..2]),
+        _ => None,
+    }
+}
+
+/// Returns the length of the prefix of `text` of the form `server\share`,
+/// where the separator may be any of `separators`.
+fn share_len(text: &str, separators: &[char]) -> Option<usize> {
+    let is_separator = |c: char| separators.contains(&c);
+    let (server_end, separator) = text.char_indices().find(|&(_, c)| is_separator(c))?;
+    let share = &text[server_end + separator.len_utf8()..];
+    let share_end = share.find(is_separator).unwrap_or(share.len());
+    (server_end > 0 && share_end > 0).then_some(text.len() - share.len() + share_end)
+}
+
+/// Returns the prefix of `path` of the form `\\server\share`, where the
+/// separators may be any of `separators`.
+fn unc_root<'a>(path: &'a str, separators: &[char]) -> Option<&'a str> {
+    let mut chars = path.char_indices();
+    if !chars.by_ref().take(2).all(|(_, c)| separators.contains(&c)) {
+        return None;
+    }
+    let start = chars.next()?.0;
+    share_len(&path[start..], separators).map(|len| &path[..start + len])
+}
+
+/// A type-erased `PlatformProperties`, which can be stored by a matcher
+#[derive(Clone)]
+pub struct PlatformPropertiesOpaque(Arc<dyn PlatformProperties + Send + Sync>, TypeId);
+
+impl<P: PlatformProperties + Send + Sync + 'static> From<P> for PlatformPropertiesOpaque {
+    fn from(properties: P) -> PlatformPropertiesOpaque {
+        PlatformPropertiesOpaque(Arc::new(properties), TypeId::of::<P>())
+    }
+}
+
+impl core::fmt::Debug for PlatformPropertiesOpaque {
+    fn fmt(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        formatter
+            .debug_struct("PlatformPropertiesOpaque")
+            .field("separators", &self.separators())
+            .finish_non_exhaustive()
+    }
+}
+
+impl PlatformPropertiesOpaque {
+    /// Returns `true` if both were constructed from the same type of
+    /// `PlatformProperties`.
+    pub(crate) fn same_type(&self, other: &PlatformPropertiesOpaque) -> bool {
+        self.1 == other.1
+    }
+
+    /// See `PlatformProperties::separators`.
+    #[must_use]
+    pub fn separators(&self) -> &[char] {
+        self.0.separators()
+    }
+
+    /// See `PlatformProperties::root_name`.
+    #[must_use]
+    pub fn root_name<'a>(&self, path: &'a str) -> Option<&'a str> {
+        self.0.root_name(path)
+    }
+
+    /// See `PlatformProperties::canonical_root`.
+    #[must_use]
+    pub fn canonical_root<'a>(&self, root: &'a str) -> Cow<'a, str> {
+        self.0.canonical_root(root)
+    }
+
+    /// See `PlatformProperties::max_root_separators`.
+    #[must_use]
+    pub fn max_root_separators(&self) -> usize {
+        self.0.max_root_separators()
+    }
+
+    /// See `PlatformProperties::case_sensitive`.
+    #[must_use]
+    pub fn case_sensitive(&self) -> bool {
+        self.0.case_sensitive()
+    }
+}
+
+/// The properties of paths on the platform being compiled for, which are
+/// those of `Windows` when targeting Windows and of `Unix` otherwise.
+#[cfg(windows)]
+pub type Current = Windows;
+
+/// The properties of paths on the platform being compiled for, which are
+/// those of `Windows` when targeting Windows and of `Unix` otherwise.
+#[cfg(not(windows))]
+pub type Current = Unix;
+
+/// Returns the properties of paths on the platform being compiled for, as
+/// described by `Current`.
+#[must_use]
+pub fn current() -> PlatformPropertiesOpaque {
+    Current::default().into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn root_names() {
+        assert_eq!(Windows.root_name(r"C:\foo"), Some("C:"));
+        assert_eq!(Windows.root_name("z:"), Some("z:"));
+        assert_eq!(Windows.root_name(r"\foo"), None);
+        assert_eq!(Windows.root_name("1:"), None);
+        assert_eq!(Unix.root_name("C:/foo"), None);
+        assert_eq!(Windows.root_name(r"\\server\share\file"), Some(r"\\server\share"));
+        assert_eq!(Windows.root_name("//server/share"), Some("//server/share"));
+        assert_eq!(Windows.root_name(r"\/server/share/"), Some(r"\/server/share"));
+        assert_eq!(Windows.root_name(r"\\server\"), None);
+        assert_eq!(Windows.root_name(r"\\server"), None);
+        assert_eq!(Windows.root_name(r"\\\share\file"), None);
+        assert_eq!(Windows.canonical_root("//server/share"), r"\\server\share");
+        assert_eq!(Windows.canonical_root("C:"), "C:");
+        assert_eq!(Windows.root_name(r"\\?\C:\foo"), Some(r"\\?\C:"));
+        assert_eq!(
+            Windows.root_name(r"\\?\UNC\server\share\a"),
+            Some(r"\\?\UNC\server\share")
+        );
+        assert_eq!(Windows.root_name(r"\\?\Volume{1}\a"), Some(r"\\?\Volume{1}"));
+        assert_eq!(Windows.root_name(r"\\?\UNC\server"), None);
+        assert_eq!(Windows.canonical_root(r"\\?\C:"), "C:");
+        assert_eq!(Windows.canonical_root(r"\\?\UNC\server\share"), r"\\server\share");
+        assert_eq!(Windows.canonical_root(r"\\?\Volume{1}"), r"\\?\Volume{1}");
+        let opaque = PlatformPropertiesOpaque::from(Windows);
+        assert_eq!(opaque.separators(), ['\\', '/']);
+        assert_eq!(opaque.root_name("d:x"), Some("d:"));
+        assert!(!opaque.case_sensitive());
+        assert!(PlatformPropertiesOpaque::from(Unix).case_sensitive());
+    }
+
+    // The separators of the target are checked at compile time
+    const _: () = {
+        let expected: &[char] = if cfg!(windows) { &['\\', '/'] } else { &['/'] };
+        assert!(Current::SEPARATORS.len() == expected.len());
+        let mut idx = 0;
+        while idx < expected.len() {
+            assert!(Current::SEPARATORS[idx] == expected[idx]);
+            idx += 1;
+        }
+    };
+
+    #[test]
+    fn current_platform() {
+        let expected = if cfg!(windows) {
+            Windows.separators()
+        } else {
+            Unix.separators()
+        };
+        assert_eq!(current().separators(), expected);
+        assert_eq!(current().case_sensitive(), !cfg!(windows));
+    }
+}
@@ -0,0 +1,161 @@
+use alloc::vec::Vec;
+
+use crate::{NameMatcher, PathComponent, PatternComponent, PatternId};
+
+/// A pattern added to a `PathMatchBuilder` which matches no path that another
+/// pattern does not, as reported by `PathMatchBuilder::build_with_diagnostics`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Redundancy {
+    /// The redundant pattern
+    pub pattern: PatternId,
+
+    /// A pattern matching every path matched by `pattern`
+    pub subsumed_by: PatternId,
+}
+
+/// Returns `true` if `outer` matches every name matched by `inner`.
+fn matcher_covers(outer: &NameMatcher, inner: &NameMatcher) -> bool {
+    match (outer, inner) {
+        _ if outer == inner => true,
+        (NameMatcher::Root(outer), NameMatcher::Root(inner))
+        | (NameMatcher::NoLeadingDot(outer), NameMatcher::NoLeadingDot(inner)) => matcher_covers(outer, inner),
+        (NameMatcher::Root(_) | NameMatcher::NoLeadingDot(_), _) | (_, NameMatcher::Root(_)) => false,
+        (_, NameMatcher::NoLeadingDot(inner)) => matcher_covers(outer, inner),
+        _ => match (outer.affixes(), inner.affixes()) {
+            // The inner prefix and suffix are at least as long, so names
+            // long enough for them are long enough for the outer ones
+            (Some(outer), Some(inner)) => inner.0.starts_with(outer.0) && inner.1.ends_with(outer.1),
+            (Some(outer), None) => outer.0.is_empty() && outer.1.is_empty(),
+            (None, _) => false,
+        },
+    }
+}
+
+/// Returns `true` if `outer` matches every path component matched by
+/// `inner`. Wildcards are only compared against literals if literals are
+/// compared exactly, since a comparator may let a literal match names the
+/// wildcard does not.
+fn component_covers(outer: &PatternComponent, inner: &PatternComponent, exact_literals: bool) -> bool {
+    match (outer, inner) {
+        _ if outer == inner => true,
+        (PatternComponent::Wildcard(outer), PatternComponent::Wildcard(inner)) => matcher_covers(outer, inner),
+        (PatternComponent::Wildcard(outer), PatternComponent::Literal(inner)) => {
+            exact_literals && outer.matches_component(inner)
+        }
+        _ => false,
+    }
+}
+
+/// Returns `true` if a single name is matched by `component`.
+fn is_single_name(component: &PatternComponent) -> bool {
+    match component {
+        PatternComponent::Literal(PathComponent::Name(_)) => true,
+        PatternComponent::Wildcard(matcher) => !matches!(matcher, NameMatcher::Root(_)),
+        _ => false,
+    }
+}
+
+/// Returns `true` if every path matched by `inner` is matched by `outer`.
+fn covers(outer: &[PatternComponent], inner: &[PatternComponent], exact_literals: bool, trailing: bool) -> bool {
+    match (outer.split_first(), inner.split_first()) {
+        (None, None) | (Some((PatternComponent::AnyPath, _)), _) => true,
+        // A pattern without a trailing separator may match a path with one
+        (None, Some((PatternComponent::Literal(PathComponent::DirectoryMarker), []))) => trailing,
+        (Some((PatternComponent::GlobStar, outer_rest)), _) => {
+            covers(outer_rest, inner, exact_literals, trailing)
+                || inner.split_first().is_some_and(|(head, inner_rest)| {
+                    (is_single_name(head) || *head == PatternComponent::GlobStar)
+                        && covers(outer, inner_rest, exact_literals, trailing)
+                })
+        }
+        (Some((outer_head, outer_rest)), Some((inner_head, inner_rest))) => {
+            component_covers(outer_head, inner_head, exact_literals)
+                && covers(outer_rest, inner_rest, exact_literals, trailing)
+        }
+        _ => false,
+    }
+}
+
+/// Finds the patterns, given as the expansions of each pattern, which are
+/// matched by another. Removed patterns, which have no expansions, are
+/// ignored.
+pub(crate) fn find(patterns: &[Vec<Vec<PatternComponent>>], exact_literals: bool, trailing: bool) -> Vec<Redundancy> {
+    let pattern_covers = |outer: &[Vec<PatternComponent>], inner: &[Vec<PatternComponent>]| {
+        inner
+            .iter()
+            .all(|inner| outer.iter().any(|outer| covers(outer, inner, exact_literals, trailing)))
+    };
+    let mut result = Vec::new();
+    for (id, pattern) in patterns.iter().enumerate() {
+        if pattern.is_empty() {
+            continue;
+        }
+        let subsumed_by = patterns.iter().enumerate().position(|(other_id, other)| {
+            // Of two equivalent patterns, only the later is redundant
+            other_id != id
+                && !other.is_empty()
+                && pattern_covers(other, pattern)
+                && (other_id < id || !pattern_covers(pattern, other))
+        });
+        if let Some(other_id) = subsumed_by {
+            result.push(Redundancy {
+                pattern: PatternId(id),
+                subsumed_by: PatternId(other_id),
+            });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Error, PathMatchBuilder};
+
+    fn redundancies(builder: PathMatchBuilder) -> Result<Vec<(usize, usize)>, Error> {
+        let (_, diagnostics) = builder.build_with_diagnostics()?;
+        Ok(diagnostics
+            .redundant
+            .into_iter()
+            .map(|r| (r.pattern.0, r.subsumed_by.0))
+            .collect())
+    }
+
+    #[test]
+    fn subsumption() -> Result<(), Error> {
+        let mut builder = PathMatchBuilder::new("/");
+        builder.add_patterns([
+            "a/*", "a/b", "a/b/", "x*.rs", "xy*.rs", "**/c", "d/e/c", "docs/...", "docs/a/*", "f", "f", "{g,h}", "g",
+            "[gh]",
+        ])?;
+        assert_eq!(
+            redundancies(builder)?,
+            [(1, 0), (2, 0), (4, 3), (6, 5), (8, 7), (10, 9), (11, 13), (12, 11)]
+        );
+
+        let mut builder = PathMatchBuilder::new("/");
+        builder.add_patterns(["a/*", "a/b/", "a/*/", "b/**/c", "b/**/d/c", "**/x*", "*y"])?;
+        builder.allow_trailing_separator(false);
+        assert_eq!(redundancies(builder)?, [(1, 2), (4, 3)]);
+
+        // A literal compared by a comparator may match names a wildcard does not
+        let mut builder = PathMatchBuilder::new("/");
+        builder.add_patterns(["a/*", "a/b", "c", "c"])?;
+        builder.literal_comparator(|a, b| a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase()));
+        assert_eq!(redundancies(builder)?, [(3, 2)]);
+
+        let mut builder = PathMatchBuilder::new("/");
+        builder.add_patterns(["a/*", "a/.b", "a/c"])?;
+        builder.no_dotfiles(true);
+        let (matcher, diagnostics) = builder.build_with_diagnostics()?;
+        assert!(matcher.matches("a/.b"));
+        assert_eq!(
+            diagnostics.redundant,
+            [Redundancy {
+                pattern: PatternId(2),
+                subsumed_by: PatternId(0)
+            }]
+        );
+        Ok(())
+    }
+}
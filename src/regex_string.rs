@@ -0,0 +1,508 @@
+//! Conversion of a `PathMatch` into an equivalent regular expression, written
+//! in the syntax of the `regex` crate.
+//!
+//! The expression matches normalized paths, in which the only `.` is a path
+//! consisting of `.` alone and every `..` precedes any name. Since no pattern
+//! matches a `..` or a lone `.`, names matched by wildcards at the start of a
+//! relative path exclude both.
+
+use alloc::string::{String, ToString as _};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use crate::name_pattern::{CharClass, Segment};
+use crate::{NameMatcher, PathComponent, PathMatch, PathMatchNode, PATH_CURRENT};
+
+/// Characters with a meaning in regular expression syntax
+const REGEX_META: &[char] = &[
+    '\\', '.', '+', '*', '?', '(', ')', '|', '[', ']', '{', '}', '^', '$', '#', '&', '-', '~',
+];
+
+/// Characters with a meaning within a class
+const CLASS_META: &[char] = &['\\', '[', ']', '^', '-', '&', '~'];
+
+/// Matches any character, including a newline
+const ANY_CHAR: &str = "(?s:.)";
+
+/// A class matching no characters, used by a matcher with no patterns
+const NOTHING: &str = r"[^\s\S]";
+
+/// What precedes the next component of a normalized path
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Position {
+    Start,
+    /// A root written as a separator
+    Root,
+    /// A named root such as `C:`
+    NamedRoot,
+    /// A name, after which a separator precedes the next component
+    Entry,
+    /// A directory marker, which ends the path
+    Directory,
+}
+
+/// A part of a name matcher. Every atom other than `Star`, `MaybeAny` and
+/// `Range` matches a single character.
+#[derive(Clone, Copy)]
+enum Atom<'a> {
+    Char(char),
+    Star,
+    /// Any single character
+    Any,
+    /// Any single character, or nothing
+    MaybeAny,
+    Class(&'a CharClass),
+    Range(u64, u64),
+}
+
+/// A restriction on the first character of a name
+#[derive(Clone, Copy)]
+enum First {
+    Dot,
+    NotDot,
+    Any,
+}
+
+fn push_literal(text: &str, output: &mut String) {
+    for c in text.chars() {
+        if REGEX_META.contains(&c) {
+            output.push('\\');
+        }
+        output.push(c);
+    }
+}
+
+fn literal(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    push_literal(text, &mut output);
+    output
+}
+
+/// Returns an alternation of `alternatives`, or `None` if there are none.
+fn group(mut alternatives: Vec<String>) -> Option<String> {
+    match alternatives.len() {
+        0 => None,
+        1 => alternatives.pop(),
+        _ => Some(format!("(?:{})", alternatives.join("|"))),
+    }
+}
+
+fn class_members(chars: &[char]) -> String {
+    let mut output = String::new();
+    for &c in chars {
+        if CLASS_META.contains(&c) {
+            output.push('\\');
+        }
+        output.push(c);
+    }
+    output
+}
+
+fn digit_class(low: u8, high: u8) -> String {
+    if low == high {
+        char::from(low).into()
+    } else {
+        format!("[{}-{}]", char::from(low), char::from(high))
+    }
+}
+
+/// Matches the decimal numbers from `low` to `high`, which have the same
+/// number of digits, written with that many digits.
+fn fixed_width_range(low: &[u8], high: &[u8]) -> String {
+    let (&low_first, low_rest) = low.split_first().expect("non-empty bound");
+    let (&high_first, high_rest) = high.split_first().expect("non-empty bound");
+    if low_rest.is_empty() {
+        return digit_class(low_first, high_first);
+    }
+    if low_first == high_first {
+        return format!("{}{}", char::from(low_first), fixed_width_range(low_rest, high_rest));
+    }
+    let any_digits = match low_rest.len() {
+        1 => String::from("[0-9]"),
+        len => format!("[0-9]{{{}}}", len),
+    };
+    if low_rest.iter().all(|&d| d == b'0') && high_rest.iter().all(|&d| d == b'9') {
+        return digit_class(low_first, high_first) + &any_digits;
+    }
+    let nines = vec![b'9'; low_rest.len()];
+    let zeros = vec![b'0'; low_rest.len()];
+    let mut alternatives = vec![format!(
+        "{}{}",
+        char::from(low_first),
+        fixed_width_range(low_rest, &nines)
+    )];
+    if low_first + 1 < high_first {
+        alternatives.push(digit_class(low_first + 1, high_first - 1) + &any_digits);
+    }
+    alternatives.push(format!(
+        "{}{}",
+        char::from(high_first),
+        fixed_width_range(&zeros, high_rest)
+    ));
+    group(alternatives).expect("non-empty alternation")
+}
+
+/// Matches a run of digits whose value lies between `low` and `high`,
+/// allowing leading zeros.
+fn numeric_range(low: u64, high: u64) -> String {
+    let digits = |value: u64| value.to_string().len();
+    let alternatives = (digits(low)..=digits(high))
+        .map(|width| {
+            let smallest = if width == 1 {
+                0
+            } else {
+                10_u128.pow(u32::try_from(width - 1).expect("small width"))
+            };
+            let largest = 10_u128.pow(u32::try_from(width).expect("small width")) - 1;
+            let low = u128::from(low).max(smallest).to_string();
+            let high = u128::from(high).min(largest).to_string();
+            fixed_width_range(low.as_bytes(), high.as_bytes())
+        })
+        .collect();
+    format!("0*{}", group(alternatives).expect("non-empty range"))
+}
+
+fn atoms(matcher: &NameMatcher) -> Vec<Atom<'_>> {
+    match matcher {
+        NameMatcher::StartsWith(_) | NameMatcher::EndsWith(_) | NameMatcher::StartsEndsWith(_) => {
+            let (prefix, suffix) = matcher.affixes().unwrap_or_default();
+            prefix
+                .chars()
+                .map(Atom::Char)
+                .chain(core::iter::once(Atom::Star))
+                .chain(suffix.chars().map(Atom::Char))
+                .collect()
+        }
+        NameMatcher::Pattern(pattern) => pattern
+            .segments()
+            .iter()
+            .flat_map(|segment| -> Vec<Atom<'_>> {
+                match segment {
+                    Segment::Literal(text) => text.chars().map(Atom::Char).collect(),
+                    Segment::Wildcard => vec![Atom::Star],
+                    Segment::AnyChars(min, max) => {
+                        let mut atoms = vec![Atom::Any; *min];
+                        atoms.resize(*max, Atom::MaybeAny);
+                        atoms
+                    }
+                    Segment::Class(class) => vec![Atom::Class(class)],
+                    Segment::NumericRange(low, high) => vec![Atom::Range(*low, *high)],
+                }
+            })
+            .collect(),
+        NameMatcher::Root(m) | NameMatcher::NoLeadingDot(m) => atoms(m),
+    }
+}
+
+struct Writer {
+    flat: bool,
+    /// The escaped separator characters, for use within a class
+    separators: String,
+    separator: String,
+}
+
+impl Writer {
+    fn new(separators: &[char], flat: bool) -> Writer {
+        let members = class_members(separators);
+        let separator = match separators {
+            [c] => literal(&c.to_string()),
+            _ => format!("[{}]", members),
+        };
+        Writer {
+            flat,
+            separators: members,
+            separator,
+        }
+    }
+
+    /// Returns a class matching a character of a name which is not in
+    /// `excluded`. `excluded` must already be escaped.
+    fn name_char(&self, excluded: &str) -> String {
+        if excluded.is_empty() && self.separators.is_empty() {
+            ANY_CHAR.into()
+        } else {
+            format!("[^{}{}]", excluded, self.separators)
+        }
+    }
+
+    fn class(&self, class: &CharClass, excluded: &str) -> String {
+        let mut members = String::new();
+        let named = class.named.iter().flat_map(|named| named.ranges());
+        for &(low, high) in class.ranges.iter().chain(named) {
+            members += &class_members(&[low]);
+            if low != high {
+                members.push('-');
+                members += &class_members(&[high]);
+            }
+        }
+        let negation = if class.negated { "^" } else { "" };
+        if excluded.is_empty() && self.separators.is_empty() {
+            format!("[{}{}]", negation, members)
+        } else {
+            format!("[[{}{}]&&{}]", negation, members, self.name_char(excluded))
+        }
+    }
+
+    fn atoms(&self, atoms: &[Atom]) -> String {
+        atoms
+            .iter()
+            .map(|atom| match atom {
+                Atom::Char(c) => literal(&c.to_string()),
+                Atom::Star => self.name_char("") + "*",
+                Atom::Any => self.name_char(""),
+                Atom::MaybeAny => self.name_char("") + "?",
+                Atom::Class(class) => self.class(class, ""),
+                Atom::Range(low, high) => numeric_range(*low, *high),
+            })
+            .collect()
+    }
+
+    /// Splits the names matched by `atoms` whose first character satisfies
+    /// `first` into that character and the rest of the name. Since a range
+    /// starts with a digit, a range is left intact by an empty first part.
+    fn split_first<'b, 'a>(&self, atoms: &'b [Atom<'a>], first: First) -> Vec<(String, &'b [Atom<'a>])> {
+        let Some((head, rest)) = atoms.split_first() else {
+            return Vec::new();
+        };
+        match (head, first) {
+            (Atom::Char(c), First::Dot) if *c != '.' => Vec::new(),
+            (Atom::Char('.'), First::NotDot) | (Atom::Range(..), First::Dot) => Vec::new(),
+            (Atom::Char(c), _) => vec![(literal(&c.to_string()), rest)],
+            (Atom::Class(class), First::Dot) if !class.matches('.') => Vec::new(),
+            (Atom::Class(_), First::Dot) => vec![(literal(PATH_CURRENT), rest)],
+            (Atom::Class(class), First::NotDot) => vec![(self.class(class, r"\."), rest)],
+            (Atom::Class(class), First::Any) => vec![(self.class(class, ""), rest)],
+            (Atom::Star | Atom::MaybeAny, _) => {
+                let mut result = self.split_first(rest, first);
+                // A `*` may match further characters, while a `?` may not
+                let remainder = if matches!(head, Atom::Star) { atoms } else { rest };
+                result.push((self.any_first(first), remainder));
+                result
+            }
+            (Atom::Any, _) => vec![(self.any_first(first), rest)],
+            (Atom::Range(..), _) => vec![(String::new(), atoms)],
+        }
+    }
+
+    /// Matches any single character of a name satisfying `first`.
+    fn any_first(&self, first: First) -> String {
+        match first {
+            First::Dot => literal(PATH_CURRENT),
+            First::NotDot => self.name_char(r"\."),
+            First::Any => self.name_char(""),
+        }
+    }
+
+    fn join(&self, parts: Vec<(String, &[Atom])>, prefix: &str) -> Vec<String> {
+        parts
+            .into_iter()
+            .map(|(head, rest)| format!("{}{}{}", prefix, head, self.atoms(rest)))
+            .collect()
+    }
+
+    /// Matches the non-empty names matched by `atoms`, excluding `.` and
+    /// `..` unless they follow an entry.
+    fn name(&self, atoms: &[Atom], position: Position) -> Option<String> {
+        if self.flat {
+            return Some(self.atoms(atoms));
+        }
+        if position == Position::Entry {
+            if atoms.iter().any(|atom| !matches!(atom, Atom::Star | Atom::MaybeAny)) {
+                // The name cannot be empty
+                return Some(self.atoms(atoms));
+            }
+            return group(self.join(self.split_first(atoms, First::Any), ""));
+        }
+        let mut alternatives = self.join(self.split_first(atoms, First::NotDot), "");
+        for (_, after_dot) in self.split_first(atoms, First::Dot) {
+            alternatives.extend(self.join(self.split_first(after_dot, First::NotDot), r"\."));
+            for (_, after_dots) in self.split_first(after_dot, First::Dot) {
+                alternatives.extend(self.join(self.split_first(after_dots, First::Any), r"\.\."));
+            }
+        }
+        group(alternatives)
+    }
+
+    /// Matches the names matched by `atoms` which do not start with `.`
+    fn visible_name(&self, atoms: &[Atom]) -> Option<String> {
+        group(self.join(self.split_first(atoms, First::NotDot), ""))
+    }
+
+    /// Returns the separator needed before a name at `position`.
+    fn separator_before(&self, position: Position) -> &str {
+        if position == Position::Entry {
+            &self.separator
+        } else {
+            ""
+        }
+    }
+
+    fn directory_marker(&self, position: Position) -> String {
+        match position {
+            Position::Entry => self.separator.clone(),
+            Position::Start | Position::Root | Position::Directory => String::new(),
+            Position::NamedRoot => literal(PATH_CURRENT) + &self.separator,
+        }
+    }
+
+    fn component(&self, component: &PathComponent, position: &mut Position, output: &mut String) {
+        match component {
+            PathComponent::RootName(root) if root.is_empty() => {
+                *output += &self.separator;
+                *position = Position::Root;
+            }
+            PathComponent::RootName(root) => {
+                push_literal(root, output);
+                *position = Position::NamedRoot;
+            }
+            PathComponent::DirectoryMarker => {
+                *output += &self.directory_marker(*position);
+                *position = Position::Directory;
+            }
+            component => {
+                *output += self.separator_before(*position);
+                push_literal(&component.to_string(), output);
+                *position = Position::Entry;
+            }
+        }
+    }
+
+    /// Matches the remainder of a path once `node` has been reached at
+    /// `position`, or returns `None` if no path can match.
+    fn node(&self, node: &PathMatchNode, mut position: Position) -> Option<String> {
+        let mut prefix = String::new();
+        for component in &node.chain {
+            self.component(component, &mut position, &mut prefix);
+        }
+        let mut alternatives = Vec::new();
+        if node.can_end {
+            let marker = self.directory_marker(position);
+            if position == Position::Root && !self.flat {
+                // A root with nothing following it is normalized to `/.`
+                alternatives.push(format!("(?:{})?", literal(PATH_CURRENT)));
+            } else if node.can_end_as_directory && !self.flat && !marker.is_empty() {
+                alternatives.push(format!("(?:{})?", marker));
+            } else {
+                alternatives.push(String::new());
+            }
+        }
+        if node.matches_descendants {
+            let descendants = if self.flat {
+                format!("{}*", ANY_CHAR)
+            } else if position == Position::Entry {
+                format!("(?:{}{}*)?", self.separator, ANY_CHAR)
+            } else {
+                // Anything other than a path starting with `..`
+                format!(
+                    r"(?:[^.]{any}*|\.(?:[^.]{any}*)?|\.\.{}{any}*)?",
+                    self.name_char(""),
+                    any = ANY_CHAR
+                )
+            };
+            alternatives.push(descendants);
+        }
+        // Literals are sorted so that the output is deterministic
+        let mut literals: Vec<_> = node.literals.iter().collect();
+        literals.sort_unstable_by_key(|&(k, _)| k);
+        for (component, child) in literals {
+            if self.flat && !matches!(component, PathComponent::Name(_)) {
+                continue;
+            }
+            let mut child_position = position;
+            let mut alternative = String::new();
+            self.component(component, &mut child_position, &mut alternative);
+            if let Some(rest) = self.node(child, child_position) {
+                alternatives.push(alternative + &rest);
+            }
+        }
+        for (matcher, child) in &node.wildcards {
+            let (name, child_position) = match matcher {
+                // A root name is never `.` or `..`
+                NameMatcher::Root(_) => (self.name(&atoms(matcher), Position::Entry), Position::NamedRoot),
+                NameMatcher::NoLeadingDot(_) => (self.visible_name(&atoms(matcher)), Position::Entry),
+                _ => (self.name(&atoms(matcher), position), Position::Entry),
+            };
+            if let (Some(name), Some(rest)) = (name, self.node(child, child_position)) {
+                alternatives.push(format!("{}{}{}", self.separator_before(position), name, rest));
+            }
+        }
+        if let Some(child) = &node.globstar {
+            if self.flat {
+                alternatives.extend(
+                    self.node(child, Position::Entry)
+                        .map(|rest| format!("{}*{}", ANY_CHAR, rest)),
+                );
+            } else {
+                let names = format!("(?:{}{}+)*", self.separator, self.name_char(""));
+                if position != Position::Entry {
+                    alternatives.extend(self.node(child, position));
+                }
+                let first = match position {
+                    Position::Entry => Some(String::new()),
+                    _ => self.name(&[Atom::Star], position),
+                };
+                if let (Some(first), Some(rest)) = (first, self.node(child, Position::Entry)) {
+                    alternatives.push(format!("{}{}{}", first, names, rest));
+                }
+            }
+        }
+        group(alternatives).map(|alternatives| prefix + &alternatives)
+    }
+}
+
+pub(crate) fn to_regex(matcher: &PathMatch) -> Option<String> {
+    if matcher.comparator.is_some() {
+        return None;
+    }
+    let separators: Vec<char> = match &matcher.platform {
+        Some(platform) => platform.separators().to_vec(),
+        None if matcher.flat => Vec::new(),
+        None => {
+            let mut chars = matcher.separator.chars();
+            let separator = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            vec![separator]
+        }
+    };
+    let writer = Writer::new(&separators, matcher.flat);
+    let body = writer.node(&matcher.match_tree, Position::Start);
+    Some(format!("^{}$", body.as_deref().unwrap_or(NOTHING)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numeric_ranges() {
+        assert_eq!(numeric_range(0, 9), "0*[0-9]");
+        assert_eq!(numeric_range(1, 100), "0*(?:[1-9]|[1-9][0-9]|100)");
+        for (low, high) in [(0, 0), (7, 7), (3, 27), (15, 342), (99, 1001), (0, 20_000)] {
+            let regex = regex::Regex::new(&format!("^{}$", numeric_range(low, high))).expect("valid regex");
+            for value in 0..25_000_u64 {
+                let expected = (low..=high).contains(&value);
+                assert_eq!(
+                    regex.is_match(&value.to_string()),
+                    expected,
+                    "{}-{} {}",
+                    low,
+                    high,
+                    value
+                );
+                assert_eq!(
+                    regex.is_match(&format!("00{}", value)),
+                    expected,
+                    "{}-{} {}",
+                    low,
+                    high,
+                    value
+                );
+            }
+        }
+        let regex = regex::Regex::new(&format!("^{}$", numeric_range(0, u64::MAX))).expect("valid regex");
+        assert!(regex.is_match(&u64::MAX.to_string()));
+        assert!(!regex.is_match("18446744073709551616"));
+    }
+}
@@ -0,0 +1,97 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::{PathComponent, PathMatchNode};
+
+/// A state of the search performed by `PathMatchNode::search`: a node, the
+/// index of the first unmatched component of the path, and the traversal
+/// depth of the remaining components.
+pub(crate) type Candidate<'a> = (&'a PathMatchNode, usize, usize);
+
+/// Buffers which can be reused between calls to
+/// `PathMatch::matches_with_scratch`, so that matching does not allocate once
+/// the buffers have grown large enough.
+///
+/// A `MatchScratch` holds no state between calls and may be used with any
+/// number of different matchers.
+///
+/// The saving is largest for long paths matched against mostly literal
+/// patterns, where allocation is a significant fraction of the total work: in
+/// the `deep_tree` benchmark, matching a 65 component path takes roughly a
+/// third less time. Where most time is spent testing wildcards, as in the
+/// `wide_wildcards` benchmark, the difference is negligible.
+#[derive(Debug, Default)]
+pub struct MatchScratch {
+    components: Vec<PathComponent<'static>>,
+    candidates: Vec<Candidate<'static>>,
+}
+
+impl MatchScratch {
+    /// Constructs a `MatchScratch` with empty buffers.
+    #[must_use]
+    pub fn new() -> MatchScratch {
+        MatchScratch::default()
+    }
+
+    pub(crate) fn take_components<'p>(&mut self) -> Vec<PathComponent<'p>> {
+        core::mem::take(&mut self.components)
+    }
+
+    pub(crate) fn return_components(&mut self, components: Vec<PathComponent>) {
+        self.components = recycle(components);
+    }
+
+    pub(crate) fn take_candidates<'a>(&mut self) -> VecDeque<Candidate<'a>> {
+        core::mem::take(&mut self.candidates).into()
+    }
+
+    pub(crate) fn return_candidates(&mut self, candidates: VecDeque<Candidate>) {
+        self.candidates = recycle(candidates.into());
+    }
+}
+
+/// Empties `vec` and returns its allocation as a vector of a type with the
+/// same layout, typically differing only in lifetimes. Collecting from the
+/// consumed iterator reuses the allocation in place.
+fn recycle<T, U>(mut vec: Vec<T>) -> Vec<U> {
+    vec.clear();
+    vec.into_iter().map(|_| unreachable!()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Error, PathMatch, PathMatchBuilder};
+
+    #[test]
+    fn scratch_agrees_with_matches() -> Result<(), Error> {
+        let mut builder = PathMatchBuilder::new("/");
+        builder.add_patterns(["a/*/c", "b/**/d", "x/...", "lit/eral", "dir/"])?;
+        let matcher = builder.build()?;
+        let literal = PathMatch::from_pattern("lit/eral", "/")?;
+        let mut scratch = MatchScratch::new();
+        let paths = [
+            "a/b/c", "a/b/d", "b/d", "b/1/2/d", "x", "x/y", "lit/eral", "lit", "dir/", "dir", "", "../a",
+        ];
+        for _ in 0..2 {
+            for path in paths {
+                assert_eq!(
+                    matcher.matches_with_scratch(path, &mut scratch),
+                    matcher.matches(path),
+                    "{}",
+                    path
+                );
+                assert_eq!(
+                    literal.matches_with_scratch(path, &mut scratch),
+                    literal.matches(path),
+                    "{}",
+                    path
+                );
+            }
+        }
+        // The buffers are retained between calls
+        assert!(scratch.components.capacity() > 0);
+        assert!(scratch.candidates.capacity() > 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,659 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{Comparator, PathComponent, PathMatch, PathMatchNode, PATH_CURRENT, PATH_PARENT};
+
+/// A position relative to a node of the match tree
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Position {
+    /// The number of components of the node's chain which have been matched
+    Chain(usize),
+
+    /// Within the names matched by the node's `**` child
+    GlobStar,
+}
+
+type State<'a> = (&'a PathMatchNode, Position);
+
+fn at_content((node, position): &State) -> bool {
+    *position == Position::Chain(node.chain.len())
+}
+
+/// The set of states reachable after matching a prefix of the path.
+struct Frontier<'a> {
+    states: Vec<State<'a>>,
+
+    /// Whether a subsequent `..` removes this frontier. This is false for the
+    /// initial frontier and for the frontier following a root.
+    removable: bool,
+}
+
+/// Matches a path which is supplied incrementally as a sequence of byte
+/// chunks.
+///
+/// Chunks need not be aligned to separators or even to UTF-8 character
+/// boundaries. Only the component currently being received is buffered,
+/// except that for a platform whose root names may contain separators, the
+/// start of the path is buffered until it must be past any root name. The
+/// matcher otherwise retains one set of candidate match states per component
+/// of the normalized path seen so far, which is what allows later `..`
+/// components to be handled without re-examining the path.
+pub struct StreamingMatcher<'a> {
+    matcher: &'a PathMatch,
+    separators: Vec<alloc::string::String>,
+    buffer: Vec<u8>,
+    components: usize,
+    dir_marker: bool,
+    frontiers: Vec<Frontier<'a>>,
+    unresolved_parents: usize,
+    /// Whether the path starts with a root name not followed by a separator,
+    /// such as `C:`, so that a `..` directly after it escapes the path
+    relative_root: bool,
+    invalid: bool,
+    /// While the buffer may hold the start of a root name, the number of
+    /// separators it holds. Separators are then kept in the buffer.
+    root_separators: Option<usize>,
+}
+
+impl<'a> StreamingMatcher<'a> {
+    /// Constructs a `StreamingMatcher` which will match the supplied path
+    /// against `matcher`.
+    #[must_use]
+    pub fn new(matcher: &'a PathMatch) -> StreamingMatcher<'a> {
+        let mut states = Vec::new();
+        push_state(&mut states, (&matcher.match_tree, Position::Chain(0)));
+        StreamingMatcher {
+            matcher,
+            separators: matcher.separators(),
+            buffer: Vec::new(),
+            components: 0,
+            dir_marker: false,
+            frontiers: alloc::vec![Frontier {
+                states,
+                removable: false,
+            }],
+            unresolved_parents: 0,
+            relative_root: false,
+            invalid: false,
+            root_separators: matcher
+                .platform
+                .as_ref()
+                .filter(|platform| platform.max_root_separators() > 0)
+                .map(|_| 0),
+        }
+    }
+
+    /// Supplies the next chunk of the path.
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.buffer.push(byte);
+            let separator_len = self
+                .separators
+                .iter()
+                .find(|separator| !separator.is_empty() && self.buffer.ends_with(separator.as_bytes()))
+                .map(String::len);
+            if let Some(separator_len) = separator_len {
+                if let Some(count) = self.root_separators.as_mut() {
+                    *count += 1;
+                    if self
+                        .matcher
+                        .platform
+                        .as_ref()
+                        .is_some_and(|p| *count > p.max_root_separators())
+                    {
+                        self.end_root();
+                    }
+                    continue;
+                }
+                self.buffer.truncate(self.buffer.len() - separator_len);
+                self.end_component(true);
+            }
+        }
+    }
+
+    /// Returns `true` if the path supplied so far matches. This gives the same
+    /// result as passing the complete path to `PathMatch::matches`, except
+    /// that paths which are not valid UTF-8 never match.
+    #[must_use]
+    pub fn finish(mut self) -> bool {
+        if self.matcher.flat {
+            return core::str::from_utf8(&self.buffer).is_ok_and(|path| self.matcher.matches(path));
+        }
+        if self.root_separators.is_some() {
+            self.end_root();
+        }
+        // The empty path is treated as `.`, so has no components to end
+        if self.components > 0 || !self.buffer.is_empty() {
+            self.end_component(false);
+        }
+        if self.invalid || self.unresolved_parents > 0 {
+            return false;
+        }
+        let mut states = self.frontiers.pop().map(|frontier| frontier.states).unwrap_or_default();
+        if self.frontiers.is_empty() {
+            // The normalized form of a path with no names is `.`
+            states = advance(&states, &PathComponent::Current, self.matcher.comparator);
+        }
+        is_match(&states, self.dir_marker, self.matcher.comparator)
+    }
+
+    /// Processes the root name at the start of the buffered path, if any,
+    /// followed by the rest of the buffer.
+    fn end_root(&mut self) {
+        self.root_separators = None;
+        let Some(platform) = &self.matcher.platform else {
+            return;
+        };
+        let buffer = core::mem::take(&mut self.buffer);
+        let Ok(path) = core::str::from_utf8(&buffer) else {
+            self.invalid = true;
+            return;
+        };
+        let mut rest = path;
+        if let Some(root) = platform.root_name(path) {
+            let canonical = platform.canonical_root(root).into_owned();
+            self.components += 1;
+            self.push_frontier(&PathComponent::RootName(canonical.into()), false);
+            rest = &path[root.len()..];
+            // As with `ComponentSpans`, a separator directly after the root
+            // name is a further root
+            if let Some(separator) = self.separators.iter().find(|s| rest.starts_with(s.as_str())) {
+                rest = &rest[separator.len()..];
+                self.push_frontier(&PathComponent::RootName("".into()), false);
+            } else {
+                self.relative_root = true;
+            }
+        }
+        self.push_bytes(rest.as_bytes());
+    }
+
+    /// Processes the buffered component. `separated` is true if it was
+    /// terminated by a separator rather than by the end of the path.
+    fn end_component(&mut self, separated: bool) {
+        let index = self.components;
+        self.components += 1;
+        self.dir_marker = false;
+        // The buffer is moved into the component, since the trie is keyed
+        // by owned components
+        let Ok(mut name) = String::from_utf8(core::mem::take(&mut self.buffer)) else {
+            self.invalid = true;
+            return;
+        };
+        let root_len = match &self.matcher.platform {
+            Some(platform) if index == 0 => platform.root_name(&name).map(str::len),
+            _ => None,
+        };
+        if let Some(root_len) = root_len {
+            let rest = name.split_off(root_len);
+            let root = match &self.matcher.platform {
+                Some(platform) => platform.canonical_root(&name).into_owned(),
+                None => name,
+            };
+            self.push_frontier(&PathComponent::RootName(root.into()), false);
+            match rest.as_str() {
+                // As with `ComponentSpans`, a separator directly after the root
+                // name is a further root
+                "" if separated => self.push_frontier(&PathComponent::RootName(rest.into()), false),
+                "" => {}
+                _ => {
+                    self.relative_root = true;
+                    self.push_name(rest);
+                }
+            }
+            return;
+        }
+        match name.as_str() {
+            "" if index == 0 => self.push_frontier(&PathComponent::RootName(name.into()), false),
+            "" => self.dir_marker = true,
+            _ => self.push_name(name),
+        }
+    }
+
+    fn push_name(&mut self, name: String) {
+        match name.as_str() {
+            PATH_CURRENT => {}
+            PATH_PARENT => self.pop_frontier(),
+            _ => self.push_frontier(&PathComponent::Name(name.into()), true),
+        }
+    }
+
+    fn push_frontier(&mut self, component: &PathComponent<'static>, removable: bool) {
+        let states = self
+            .frontiers
+            .last()
+            .map(|frontier| advance(&frontier.states, component, self.matcher.comparator))
+            .unwrap_or_default();
+        self.frontiers.push(Frontier { states, removable });
+    }
+
+    fn pop_frontier(&mut self) {
+        match self.frontiers.last() {
+            Some(frontier) if frontier.removable => drop(self.frontiers.pop()),
+            // A `..` at the root is a no-op
+            _ if self.frontiers.len() > 1 && !self.relative_root => {}
+            _ => self.unresolved_parents += 1,
+        }
+    }
+}
+
+/// Returns `true` if a path reaching `states` matches, optionally with a
+/// trailing separator.
+fn is_match(states: &[State], dir_marker: bool, compare: Option<Comparator>) -> bool {
+    let ends = |states: &[State], as_directory: bool| {
+        states.iter().filter(|state| at_content(state)).any(|(node, _)| {
+            node.matches_descendants
+                || if as_directory {
+                    node.can_end_as_directory
+                } else {
+                    node.can_end
+                }
+        })
+    };
+    if dir_marker {
+        ends(states, true) || ends(&advance(states, &PathComponent::DirectoryMarker, compare), false)
+    } else {
+        ends(states, false)
+    }
+}
+
+/// Returns `true` if a path reaching `state` could be extended by further
+/// names to reach a match.
+fn can_continue((node, position): &State) -> bool {
+    match *position {
+        Position::GlobStar => true,
+        Position::Chain(offset) if offset < node.chain.len() => matches!(node.chain[offset], PathComponent::Name(_)),
+        Position::Chain(_) => {
+            node.matches_descendants
+                || node.globstar.is_some()
+                || !node.wildcards.is_empty()
+                || node
+                    .literals
+                    .keys()
+                    .any(|literal| matches!(literal, PathComponent::Name(_)))
+        }
+    }
+}
+
+/// Adds `state` to `states` if not already present, along with the states
+/// reachable from it through a `**` matching no names.
+fn push_state<'a>(states: &mut Vec<State<'a>>, state: State<'a>) {
+    if states
+        .iter()
+        .any(|(node, position)| core::ptr::eq(*node, state.0) && *position == state.1)
+    {
+        return;
+    }
+    states.push(state);
+    let (node, position) = state;
+    match (position, &node.globstar) {
+        (Position::GlobStar, Some(globstar)) => push_state(states, (globstar, Position::Chain(0))),
+        (Position::Chain(_), Some(_)) if at_content(&state) => push_state(states, (node, Position::GlobStar)),
+        _ => {}
+    }
+}
+
+/// Returns the states reachable from `states` by matching `component`.
+fn advance<'a>(
+    states: &[State<'a>],
+    component: &PathComponent<'static>,
+    compare: Option<Comparator>,
+) -> Vec<State<'a>> {
+    let mut result = Vec::new();
+    for &(node, position) in states {
+        let offset = match position {
+            Position::GlobStar => {
+                if let PathComponent::Name(_) = component {
+                    push_state(&mut result, (node, position));
+                }
+                continue;
+            }
+            Position::Chain(offset) => offset,
+        };
+        if let Some(expected) = node.chain.get(offset) {
+            if component.equals(expected, compare) {
+                push_state(&mut result, (node, Position::Chain(offset + 1)));
+            }
+            continue;
+        }
+        // A `...` matches names and roots, but not a parent traversal
+        if node.matches_descendants && *component != PathComponent::Parent {
+            push_state(&mut result, (node, position));
+        }
+        node.for_each_literal_child(component, compare, |child| {
+            push_state(&mut result, (child, Position::Chain(0)));
+        });
+        for (name_matcher, child) in &node.wildcards {
+            if name_matcher.matches_component(component) {
+                push_state(&mut result, (child, Position::Chain(0)));
+            }
+        }
+    }
+    result
+}
+
+/// Returns the maximum depth of a path matching `matcher` and starting with
+/// the normalized path `components`.
+pub(crate) fn prefix_match_depth(matcher: &PathMatch, components: &[PathComponent]) -> Option<usize> {
+    let mut states = Vec::new();
+    push_state(&mut states, (&matcher.match_tree, Position::Chain(0)));
+    let mut consumed = 0usize;
+    for component in components {
+        if matches!(component, PathComponent::Current | PathComponent::DirectoryMarker) {
+            continue;
+        }
+        states = advance(&states, &component.clone().into_owned(), matcher.comparator);
+        consumed += component.traversal_depth();
+    }
+    states
+        .iter()
+        .map(|(node, position)| match *position {
+            Position::GlobStar => usize::MAX,
+            Position::Chain(offset) => {
+                let chain_depth: usize = node.chain[..offset].iter().map(PathComponent::traversal_depth).sum();
+                match node.max_traversals {
+                    usize::MAX => usize::MAX,
+                    max => max - chain_depth,
+                }
+            }
+        })
+        .max()
+        .map(|remaining| consumed.saturating_add(remaining))
+}
+
+/// Whether a path, or any path below it, can match
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorState {
+    /// The path matches. Paths below it may also match.
+    Matched,
+
+    /// The path does not match, but a path below it may. This includes a path
+    /// which only matches as a directory, since the path followed by a
+    /// separator is below it.
+    CanMatchLater,
+
+    /// Neither the path nor any path below it can match
+    DeadEnd,
+}
+
+/// Matches a relative path which is built one component at a time, such as
+/// during a directory traversal.
+///
+/// The cursor retains the candidate match states for each component of the
+/// path, so that a subtree can be pruned as soon as the cursor reports
+/// `CursorState::DeadEnd`. Cloning a cursor is cheap, allowing one clone to
+/// be kept for each directory being visited.
+#[derive(Clone)]
+pub struct PathMatchCursor<'a> {
+    matcher: &'a PathMatch,
+    frontiers: Vec<Vec<State<'a>>>,
+    /// The number of `..` components which escaped the start of the path
+    escaped: usize,
+}
+
+impl<'a> PathMatchCursor<'a> {
+    /// Constructs a `PathMatchCursor` for the empty path, which is matched as
+    /// `.`.
+    #[must_use]
+    pub fn new(matcher: &'a PathMatch) -> PathMatchCursor<'a> {
+        let mut states = Vec::new();
+        push_state(&mut states, (&matcher.match_tree, Position::Chain(0)));
+        PathMatchCursor {
+            matcher,
+            frontiers: alloc::vec![states],
+            escaped: 0,
+        }
+    }
+
+    /// Appends `component` to the path and returns the state of the resulting
+    /// path. `component` is a single name and is not split on separators.
+    ///
+    /// An empty component or `.` leaves the path unchanged, and `..` removes
+    /// the last name. A path in which `..` escapes the start can never match.
+    /// For a matcher constructed by `from_flat_pattern`, `.` and `..` are
+    /// ordinary names.
+    pub fn advance(&mut self, component: &str) -> CursorState {
+        match component {
+            _ if self.matcher.flat => self.push(component),
+            "" | PATH_CURRENT => {}
+            PATH_PARENT if self.frontiers.len() > 1 => drop(self.frontiers.pop()),
+            PATH_PARENT => self.escaped += 1,
+            _ => self.push(component),
+        }
+        self.state()
+    }
+
+    fn push(&mut self, component: &str) {
+        let states = match self.frontiers.last() {
+            Some(states) if self.escaped == 0 => advance(
+                states,
+                &PathComponent::Name(String::from(component).into()),
+                self.matcher.comparator,
+            ),
+            _ => Vec::new(),
+        };
+        self.frontiers.push(states);
+    }
+
+    /// Returns the state of the path built so far.
+    #[must_use]
+    pub fn state(&self) -> CursorState {
+        let states = self.states();
+        if self.escaped > 0 {
+            CursorState::DeadEnd
+        } else if is_match(&self.matched_states(states), false, self.matcher.comparator) {
+            CursorState::Matched
+        } else if states.iter().any(can_continue) || self.matches_directory() {
+            CursorState::CanMatchLater
+        } else {
+            CursorState::DeadEnd
+        }
+    }
+
+    /// Returns `true` if the path built so far matches when followed by a
+    /// separator, as is usual when the path is a directory.
+    #[must_use]
+    pub fn matches_directory(&self) -> bool {
+        self.escaped == 0 && is_match(&self.matched_states(self.states()), true, self.matcher.comparator)
+    }
+
+    fn states(&self) -> &[State<'a>] {
+        self.frontiers.last().map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the states against which the path is matched. The normalized
+    /// form of a path with no names is `.`.
+    fn matched_states(&self, states: &[State<'a>]) -> Vec<State<'a>> {
+        if self.frontiers.len() == 1 && !self.matcher.flat {
+            advance(states, &PathComponent::Current, self.matcher.comparator)
+        } else {
+            states.to_vec()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Error, PathMatchBuilder};
+
+    #[test]
+    fn chunked_input() -> Result<(), Error> {
+        for separator in ["/", "::"] {
+            let mut builder = PathMatchBuilder::new(separator);
+            for pattern in ["a/b/c/d", "x/*.txt/", "/abs/ün*", "a/...", "g/**/h/**", "**/z"] {
+                builder.add_pattern(pattern)?;
+            }
+            let matcher = builder.build()?;
+            let paths = [
+                "",
+                ".",
+                "a",
+                "a/",
+                "a/b",
+                "a/b/c/d/",
+                "b/b/c/d",
+                "a/b/../b/c/d",
+                "x/y.txt",
+                "x/y.txt/",
+                "x/y.txt/.",
+                "./x//y.txt//",
+                "/abs/ünicode",
+                "/../abs/ünicode/",
+                "abs/ünicode",
+                "../a/b",
+                "q/../a/b",
+                "/",
+                "//",
+                "g/h",
+                "g/a/b/h/c",
+                "g/h/../x/h/",
+                "g",
+                "z",
+                "a/b/z",
+                "x/../z",
+            ];
+            for path in paths {
+                let path = path.replace('/', separator);
+                let expected = matcher.matches(&path);
+
+                let mut whole = matcher.streaming();
+                whole.push_bytes(path.as_bytes());
+                assert_eq!(whole.finish(), expected, "{}", path);
+
+                let mut bytewise = matcher.streaming();
+                for byte in path.as_bytes() {
+                    bytewise.push_bytes(core::slice::from_ref(byte));
+                }
+                assert_eq!(bytewise.finish(), expected, "{}", path);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn cursor_descent() -> Result<(), Error> {
+        let mut builder = PathMatchBuilder::new("/");
+        for pattern in ["a/b/c", "a/*/d", "a/b*/e/", "x/**/y", "m/..."] {
+            builder.add_pattern(pattern)?;
+        }
+        let matcher = builder.build()?;
+        let mut cursor = matcher.cursor();
+        assert_eq!(cursor.state(), CursorState::CanMatchLater);
+        assert_eq!(cursor.advance("a"), CursorState::CanMatchLater);
+        // Both `b` and `*` are live below `a`
+        let at_a = cursor.clone();
+        assert_eq!(cursor.advance("b"), CursorState::CanMatchLater);
+        let at_b = cursor.clone();
+        assert_eq!(cursor.advance("c"), CursorState::Matched);
+        assert_eq!(cursor.advance("z"), CursorState::DeadEnd);
+        assert_eq!(cursor.advance(".."), CursorState::Matched);
+
+        let mut cursor = at_b.clone();
+        assert_eq!(cursor.advance("d"), CursorState::Matched);
+        let mut cursor = at_b;
+        assert_eq!(cursor.advance("e"), CursorState::CanMatchLater);
+        assert!(cursor.matches_directory());
+        let mut cursor = at_a;
+        assert_eq!(cursor.advance("q"), CursorState::CanMatchLater);
+        assert_eq!(cursor.advance("c"), CursorState::DeadEnd);
+        assert_eq!(cursor.advance(".."), CursorState::CanMatchLater);
+        assert_eq!(cursor.advance("d"), CursorState::Matched);
+
+        let mut cursor = matcher.cursor();
+        for (component, expected) in [
+            ("x", CursorState::CanMatchLater),
+            ("y", CursorState::Matched),
+            ("y", CursorState::Matched),
+            ("z", CursorState::CanMatchLater),
+            (".", CursorState::CanMatchLater),
+            ("", CursorState::CanMatchLater),
+        ] {
+            assert_eq!(cursor.advance(component), expected, "{}", component);
+        }
+
+        let mut cursor = matcher.cursor();
+        assert_eq!(cursor.advance("m"), CursorState::Matched);
+        assert_eq!(cursor.advance("n"), CursorState::Matched);
+        assert_eq!(cursor.advance("q"), CursorState::Matched);
+
+        // A path escaping its start never matches, even after returning
+        let everything = PathMatch::from_pattern("...", "/")?;
+        let mut cursor = everything.cursor();
+        assert_eq!(cursor.state(), CursorState::Matched);
+        assert_eq!(cursor.advance(".."), CursorState::DeadEnd);
+        assert_eq!(cursor.advance("a"), CursorState::DeadEnd);
+        assert_eq!(cursor.advance(".."), CursorState::DeadEnd);
+        Ok(())
+    }
+
+    #[test]
+    fn cursor_directory_only() -> Result<(), Error> {
+        // A path matching only as a directory is not a dead end
+        for pattern in ["a*b/", "?{1,2}/", "x/"] {
+            let matcher = PathMatch::from_pattern(pattern, "/")?;
+            let mut cursor = matcher.cursor();
+            let path = if pattern == "x/" { "x" } else { "ab" };
+            assert_eq!(cursor.advance(path), CursorState::CanMatchLater, "{}", pattern);
+            assert!(cursor.matches_directory(), "{}", pattern);
+            assert!(matcher.matches_prefix(path), "{}", pattern);
+            assert_eq!(cursor.advance("c"), CursorState::DeadEnd, "{}", pattern);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn cursor_agrees_with_matches() -> Result<(), Error> {
+        let mut builder = PathMatchBuilder::new("/");
+        for pattern in ["a/b/c/d", "x/*.txt/", "a/...", "g/**/h/**", "**/z", "."] {
+            builder.add_pattern(pattern)?;
+        }
+        let matcher = builder.build()?;
+        for path in [
+            "",
+            "a",
+            "a/b",
+            "b/b/c/d",
+            "x/y.txt",
+            "g/h",
+            "g/a/b/h/c",
+            "g",
+            "z",
+            "a/b/z",
+            "q/../z",
+        ] {
+            let mut cursor = matcher.cursor();
+            let components: Vec<_> = path.split('/').filter(|c| !c.is_empty()).collect();
+            for component in &components {
+                cursor.advance(component);
+            }
+            assert_eq!(
+                cursor.state() == CursorState::Matched,
+                matcher.matches(path),
+                "{}",
+                path
+            );
+            assert_eq!(
+                cursor.matches_directory(),
+                matcher.matches(format!("./{}/", path)),
+                "{}",
+                path
+            );
+            assert_eq!(
+                cursor.state() != CursorState::DeadEnd,
+                matcher.matches_prefix(path),
+                "{}",
+                path
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_utf8() -> Result<(), Error> {
+        let matcher = PathMatch::from_pattern("*", "/")?;
+        let mut streaming = matcher.streaming();
+        streaming.push_bytes(&[0xff, 0xfe]);
+        assert!(!streaming.finish());
+        Ok(())
+    }
+}
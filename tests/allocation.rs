@@ -0,0 +1,64 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use simple_path_match::{MatchScratch, PathMatch};
+
+/// Counts the allocations made by the current thread, so that tests running
+/// in parallel do not affect each other
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations made while running `f`.
+fn allocations<R>(f: impl FnOnce() -> R) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    std::hint::black_box(f());
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn borrowed_paths_do_not_allocate_per_component() -> Result<(), simple_path_match::Error> {
+    let matcher = PathMatch::try_from_patterns("/", ["src/*/lib.rs", "docs/...", "a/*/c"])?;
+    let long = ["src"; 40].join("/") + "/lib.rs";
+    let paths = ["src/x/lib.rs", long.as_str(), "./docs/./x/y.md/..", "a/b/../b/c/", "x"];
+    let mut scratch = MatchScratch::new();
+    for path in paths {
+        matcher.matches_with_scratch(path, &mut scratch);
+    }
+    for path in paths {
+        // Once the scratch buffers have grown, copying a component would be
+        // the only source of allocation
+        assert_eq!(
+            allocations(|| matcher.matches_with_scratch(path, &mut scratch)),
+            0,
+            "{}",
+            path
+        );
+    }
+
+    // Without a scratch buffer, only the buffer holding the components is
+    // allocated, however long the names are
+    let short_names = ["n"; 8].join("/");
+    let long_names = vec!["n".repeat(100); 8].join("/");
+    assert_eq!(
+        allocations(|| matcher.matches(&short_names)),
+        allocations(|| matcher.matches(&long_names))
+    );
+    Ok(())
+}
@@ -0,0 +1,5 @@
+#[test]
+fn path_match_rejects_invalid_patterns() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,3 @@
+fn main() {
+    let _ = simple_path_match::path_match!("src/[ab", r"\");
+}